@@ -5,7 +5,11 @@ use std::path::Path;
 use atty::Stream;
 use clap::{crate_authors, crate_description, crate_name, crate_version, App, Arg};
 use colored_json::prelude::*;
-use decart::{from_file, OctoCart};
+use colored_json::Output;
+use decart::{
+    cart_info, encode_octocart, from_file, from_file_with_options, thumbnail_ascii, DecodeOptions,
+    EncodeOptions, OctoCart, DEFAULT_MAX_BYTES, DEFAULT_MAX_FRAMES,
+};
 
 use syntect::easy::HighlightLines;
 use syntect::highlighting::{Style, ThemeSet};
@@ -13,6 +17,8 @@ use syntect::parsing::{SyntaxDefinition, SyntaxSetBuilder};
 use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
 
 pub fn main() {
+    let default_max_bytes = DEFAULT_MAX_BYTES.to_string();
+    let default_max_frames = DEFAULT_MAX_FRAMES.to_string();
     let matches = App::new(crate_name!())
         .version(crate_version!())
         .author(crate_authors!())
@@ -25,6 +31,16 @@ pub fn main() {
                 .long("print-program")
                 .help("instead of printing the entire JSON payload to stdout, print just the Octo program source code.")
             )
+            .arg(Arg::with_name("termin-8 config")
+                .short('t')
+                .long("termin8-config")
+                .help("Instead of printing the entire JSON payload to stdout, print just the runtime options in the .octo.rc format used by C-Octo and termin-8.")
+            )
+            .arg(Arg::with_name("card")
+                .long("card")
+                .help("Instead of printing the entire JSON payload to stdout, print a short summary of the cart for sharing it, \
+                with its title, author, platform, tickrate, colors and program length.")
+            )
             .arg(Arg::with_name("write files")
                 .short('w')
                 .long("write-files")
@@ -37,6 +53,50 @@ pub fn main() {
                 .min_values(0)
                 .max_values(1)
             )
+            .arg(Arg::with_name("max bytes")
+                .long("max-bytes")
+                .takes_value(true)
+                .value_name("BYTES")
+                .default_value(&default_max_bytes)
+                .help("Refuse to decode Octocarts with a payload larger than this many bytes.")
+            )
+            .arg(Arg::with_name("max frames")
+                .long("max-frames")
+                .takes_value(true)
+                .value_name("FRAMES")
+                .default_value(&default_max_frames)
+                .help("Refuse to decode Octocarts that need more than this many frames for their payload.")
+            )
+            .arg(Arg::with_name("lossy utf8")
+                .long("lossy-utf8")
+                .help("Replace invalid UTF-8 in the payload with replacement characters, instead of refusing to decode it.")
+            )
+            .arg(Arg::with_name("json theme")
+                .long("json-theme")
+                .takes_value(true)
+                .value_name("THEME")
+                .possible_values(["auto", "jq", "none"])
+                .default_value("auto")
+                .help("How to color the JSON payload: \"jq\" always colors it like jq does, \"none\" disables coloring, \
+                and \"auto\" colors it only when printing to a terminal and the NO_COLOR environment variable isn't set.")
+            )
+            .arg(
+                Arg::with_name("OCTOCART")
+                .help("Octo cartridge file (GIF)")
+                .required(true)
+                .value_name("OCTOCART")
+            )
+        )
+        .subcommand(
+            App::new("info")
+            .about("Show information about an Octocart's GIF image, along with a thumbnail of it.")
+            .arg(Arg::with_name("columns")
+                .long("columns")
+                .takes_value(true)
+                .value_name("COLUMNS")
+                .default_value("40")
+                .help("The width of the thumbnail, in characters.")
+            )
             .arg(
                 Arg::with_name("OCTOCART")
                 .help("Octo cartridge file (GIF)")
@@ -44,15 +104,60 @@ pub fn main() {
                 .value_name("OCTOCART")
             )
         )
+        .subcommand(
+            App::new("compare")
+            .about("Compare the programs of two Octocarts, printing a unified diff of their source code to stdout.")
+            .arg(
+                Arg::with_name("OLD")
+                .help("The original Octo cartridge file (GIF)")
+                .required(true)
+                .value_name("OLD")
+            )
+            .arg(
+                Arg::with_name("NEW")
+                .help("The updated Octo cartridge file (GIF)")
+                .required(true)
+                .value_name("NEW")
+            )
+        )
         .subcommand(
             App::new("encode")
-            .about("Encode an Octocart")
+            .about("Encode an Octocart from an Octo program and its runtime options.")
+            .arg(Arg::with_name("options")
+                .short('r')
+                .long("options")
+                .takes_value(true)
+                .value_name("OCTO.RC")
+                .help("An .octo.rc file with the runtime options. If not supplied, the default options are used.")
+            )
+            .arg(Arg::with_name("output")
+                .short('o')
+                .long("output")
+                .takes_value(true)
+                .value_name("OUTPUT")
+                .help("The file to write the Octocart to. Use \"-\" to write the GIF to stdout, for piping it to other programs; \
+                any messages are then printed to stderr. If not supplied, the filename of the program is used, with a .gif extension.")
+            )
+            .arg(
+                Arg::with_name("PROGRAM")
+                .help("Octo program source code file (.8o)")
+                .required(true)
+                .value_name("PROGRAM")
+            )
         )
         .get_matches();
 
     if let Some(matches) = matches.subcommand_matches("decode") {
         let filename = Path::new(matches.value_of("OCTOCART").unwrap());
-        let cart: OctoCart = from_file(filename).expect("Failed to read Octocart file");
+        let options = DecodeOptions {
+            max_bytes: matches.value_of_t("max bytes").unwrap_or_else(|e| e.exit()),
+            lossy_utf8: matches.is_present("lossy utf8"),
+            max_frames: matches
+                .value_of_t("max frames")
+                .unwrap_or_else(|e| e.exit()),
+        };
+        let cart: OctoCart =
+            from_file_with_options(filename, &options).expect("Failed to read Octocart file");
 
         if matches.is_present("write files") {
             let path = filename.parent().unwrap();
@@ -69,13 +174,17 @@ pub fn main() {
             let mut rc_file = File::create(Path::new(&rc_file_path))
                 .unwrap_or_else(|_| panic!("Failed to create {}", rc_file_path));
             rc_file
-                .write_all(octopt::Options::to_ini(cart.options).as_bytes())
+                .write_all(cart.to_termin8_config().as_bytes())
                 .unwrap_or_else(|_| panic!("Failed to write options to {}", rc_file_path));
 
             println!(
                 "Wrote files:\nCode:    {}\nOptions: {}",
                 octo_file_path, rc_file_path
             );
+        } else if matches.is_present("card") {
+            print!("{}", cart.summary_card());
+        } else if matches.is_present("termin-8 config") {
+            print!("{}", cart.to_termin8_config());
         } else if matches.is_present("print program") {
             if atty::is(Stream::Stdout) {
                 let mut ssb = SyntaxSetBuilder::new();
@@ -106,14 +215,130 @@ pub fn main() {
                 println!("{}", &cart.program);
             }
         } else {
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+            let color_mode = json_color_mode(matches.value_of("json theme").unwrap(), no_color);
             println!(
                 "{}",
                 cart.to_string()
-                    .to_colored_json_auto()
+                    .to_colored_json(color_mode)
                     .expect("Failed to print Octocart JSON payload")
             );
         }
-    } else if let Some(_matches) = matches.subcommand_matches("encode") {
-        todo!();
+    } else if let Some(matches) = matches.subcommand_matches("info") {
+        let filename = matches.value_of("OCTOCART").unwrap();
+        let columns: usize = matches.value_of_t("columns").unwrap_or_else(|e| e.exit());
+        let info = cart_info(File::open(filename).expect("Failed to open Octocart file"))
+            .expect("Failed to read Octocart file");
+        let thumbnail = thumbnail_ascii(
+            File::open(filename).expect("Failed to open Octocart file"),
+            columns,
+        )
+        .expect("Failed to read Octocart file");
+        let cart: OctoCart = from_file(filename).expect("Failed to read Octocart file");
+        print!("{}", thumbnail);
+        println!(
+            "Size:    {}x{}\nFrames:  {}\nPayload: {} bytes\nProgram: {}",
+            info.width,
+            info.height,
+            info.frame_count,
+            info.payload_length,
+            cart.program_stats()
+        );
+    } else if let Some(matches) = matches.subcommand_matches("compare") {
+        let old: OctoCart =
+            from_file(matches.value_of("OLD").unwrap()).expect("Failed to read Octocart file");
+        let new: OctoCart =
+            from_file(matches.value_of("NEW").unwrap()).expect("Failed to read Octocart file");
+        print!("{}", old.diff_program(&new));
+    } else if let Some(matches) = matches.subcommand_matches("encode") {
+        let filename = Path::new(matches.value_of("PROGRAM").unwrap());
+        let program = std::fs::read_to_string(filename).expect("Failed to read program file");
+        let options = match matches.value_of("options") {
+            Some(rc_file_path) => octopt::Options::from_ini(
+                &std::fs::read_to_string(rc_file_path).expect("Failed to read options file"),
+            )
+            .expect("Failed to parse options file"),
+            None => octopt::Options::default(),
+        };
+        let cart = OctoCart {
+            program,
+            options,
+            title: None,
+            author: None,
+        };
+        let default_output = filename.with_extension("gif").display().to_string();
+        let output = matches.value_of("output").unwrap_or(&default_output);
+        write_cart(
+            &cart,
+            output,
+            &mut std::io::stdout(),
+            &mut std::io::stderr(),
+        )
+        .expect("Failed to write Octocart");
+    }
+}
+
+/// Encodes the cart to the file at `output`, or to `stdout` if `output` is "-". In that case, the
+/// success message is written to `stderr` instead, so it doesn't end up in the GIF.
+fn write_cart(
+    cart: &OctoCart,
+    output: &str,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<(), decart::Error> {
+    if output == "-" {
+        encode_octocart(&mut *stdout, cart, &EncodeOptions::default())?;
+        stdout.flush()?;
+        writeln!(stderr, "Wrote Octocart to stdout")?;
+    } else {
+        encode_octocart(File::create(output)?, cart, &EncodeOptions::default())?;
+        writeln!(stdout, "Wrote Octocart: {}", output)?;
+    }
+    Ok(())
+}
+
+fn json_color_mode(theme: &str, no_color: bool) -> ColorMode {
+    match theme {
+        "jq" => ColorMode::On,
+        "none" => ColorMode::Off,
+        _ if no_color => ColorMode::Off,
+        _ => ColorMode::Auto(Output::StdOut),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_color_disables_coloring() {
+        let payload = r#"{"program":": main","options":{"tickrate":7}}"#;
+        let colored = payload.to_colored_json(ColorMode::On).unwrap();
+        assert!(colored.contains('\x1b'));
+
+        assert!(json_color_mode("auto", true) == ColorMode::Off);
+        assert!(json_color_mode("none", false) == ColorMode::Off);
+        assert!(json_color_mode("jq", true) == ColorMode::On);
+        let plain = payload
+            .to_colored_json(json_color_mode("auto", true))
+            .unwrap();
+        assert!(!plain.contains('\x1b'));
+    }
+
+    #[test]
+    fn encode_to_stdout() {
+        let cart = OctoCart {
+            program: ": main\n  loop again".to_string(),
+            options: octopt::Options::default(),
+            title: None,
+            author: None,
+        };
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        write_cart(&cart, "-", &mut stdout, &mut stderr).unwrap();
+        assert!(stdout.starts_with(b"GIF89a"));
+        assert!(!stderr.is_empty());
+        let payload = decart::decode_octocart(&stdout[..]).unwrap();
+        assert_eq!(payload, cart.to_string());
     }
 }
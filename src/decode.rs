@@ -0,0 +1,782 @@
+//! Decoding of Octocarts.
+
+use crate::{parse_payload, Error, OctoCart, MIN_PALETTE_SIZE};
+use octopt::Options;
+use std::cmp::Ordering;
+use std::io::{ErrorKind, Read, Seek};
+
+/// The default for [`DecodeOptions::max_bytes`], 4 MiB.
+pub const DEFAULT_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// The default for [`DecodeOptions::max_frames`], which is far more than any real Octocart needs.
+pub const DEFAULT_MAX_FRAMES: usize = 4096;
+
+/// Options for decoding an Octocart.
+#[derive(Debug)]
+pub struct DecodeOptions {
+    /// The largest payload, in bytes, that will be accepted. The payload's length is declared up
+    /// front in the Octocart, so carts claiming to be larger than this are rejected before their
+    /// payload is read. This bounds the memory used when decoding untrusted carts.
+    pub max_bytes: usize,
+    /// Whether invalid UTF-8 in the payload should be replaced with U+FFFD REPLACEMENT CHARACTER,
+    /// rather than failing with [`Error::Utf8Error`]. Decoding is strict by default, which catches
+    /// corrupted carts; lossy decoding is useful for recovering as much of them as possible.
+    pub lossy_utf8: bool,
+    /// The largest number of frames that will be read, including empty ones, before failing with
+    /// [`Error::TooManyFrames`]. This bounds the work done when decoding untrusted carts that
+    /// flood the decoder with frames. Frames after the end of the payload are never read, so they
+    /// don't count towards the limit.
+    pub max_frames: usize,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_BYTES,
+            lossy_utf8: false,
+            max_frames: DEFAULT_MAX_FRAMES,
+        }
+    }
+}
+
+/// How the bits of the payload are stored in the colors of an Octocart's pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BitLayout {
+    /// The Octocart format, with a nybble in every pixel: 1 bit in the least significant bit of
+    /// the red and blue channels each, and 2 in the green channel
+    #[default]
+    Octo,
+    /// A single bit in every pixel, in the least significant bit of the red channel, so that each
+    /// byte takes 8 pixels, most significant bit first. This is what encoders with a known bug
+    /// produce: they write each byte's bits one at a time into the red channel, and never touch
+    /// the green and blue channels, so the carts are unreadable by Octo.
+    RedOnly,
+}
+
+impl BitLayout {
+    /// The number of payload bits stored in each pixel.
+    fn bits(self) -> usize {
+        match self {
+            Self::Octo => 4,
+            Self::RedOnly => 1,
+        }
+    }
+
+    /// Extracts the payload bits stored in a color.
+    fn extract(self, color: (u8, u8, u8)) -> u8 {
+        match self {
+            Self::Octo => nybble(color),
+            Self::RedOnly => color.0 & 1,
+        }
+    }
+}
+
+/// Information about the GIF image of an Octocart.
+#[derive(Debug)]
+pub struct CartInfo {
+    /// The width of the GIF's logical screen
+    pub width: u16,
+    /// The height of the GIF's logical screen
+    pub height: u16,
+    /// The number of frames in the GIF, including any that don't carry the payload
+    pub frame_count: usize,
+    /// The length of the payload, in bytes, as declared in the Octocart's header
+    pub payload_length: u32,
+}
+
+/// Reads information about an Octocart's GIF image, without decoding its payload.
+///
+/// Example
+/// ```no_run
+/// use decart::*;
+/// let info = cart_info(std::fs::File::open("test_octocart.gif").unwrap()).unwrap();
+/// let cart: OctoCart = from_file("test_octocart.gif").unwrap();
+/// let options = EncodeOptions {
+///     target_frames: Some(info.frame_count),
+///     ..EncodeOptions::default()
+/// };
+/// encode_octocart(std::fs::File::create("copy.gif").unwrap(), &cart, &options).unwrap();
+/// ```
+/// # Errors
+///
+/// Returns `Err` if there is a GIF decoding error.
+pub fn cart_info<R: Read>(input: R) -> Result<CartInfo, Error> {
+    let mut decoder = gif::DecodeOptions::new().read_info(RetryInterrupted(input))?;
+    let global_palette = decoder
+        .global_palette()
+        .ok_or(Error::PaletteError)?
+        .to_vec();
+    let (width, height) = (decoder.width(), decoder.height());
+    let mut payload_length = None;
+    let mut frame_count = 0;
+    while let Some(frame) = decoder.read_next_frame()? {
+        // The header is in the first frame that has any pixels and isn't cover-only
+        if payload_length.is_none() && !frame.buffer.is_empty() && !is_cover_frame(frame) {
+            if frame.buffer.len() < 8 {
+                return Err(Error::DimensionsError);
+            }
+            let palette = Palette::new(
+                frame.palette.as_ref().unwrap_or(&global_palette),
+                frame.buffer.iter().max().copied().unwrap_or(0),
+            )?;
+            payload_length = Some(header(&frame.buffer, &palette));
+        }
+        frame_count += 1;
+    }
+    Ok(CartInfo {
+        width,
+        height,
+        frame_count,
+        payload_length: payload_length.unwrap_or(0),
+    })
+}
+
+/// The characters used to draw thumbnails, from darkest to brightest.
+const THUMBNAIL_RAMP: &[u8] = b" .:-=+*#%@";
+
+/// Renders the first frame of an Octocart's GIF image as an ASCII art thumbnail that is `columns`
+/// characters wide, for previewing a cart in a terminal.
+///
+/// The frame is downsampled by averaging the brightness of the pixels covered by each character.
+/// Since characters in a terminal are about twice as tall as they are wide, the thumbnail has half
+/// as many rows as needed to preserve the frame's aspect ratio. Each row ends with a newline.
+///
+/// Example
+/// ```no_run
+/// let file = std::fs::File::open("test_octocart.gif").unwrap();
+/// print!("{}", decart::thumbnail_ascii(file, 40).unwrap());
+/// ```
+/// # Errors
+///
+/// Returns `Err` if there is a GIF decoding error, or if the GIF has no (non-empty) frames.
+pub fn thumbnail_ascii<R: Read>(input: R, columns: usize) -> Result<String, Error> {
+    let mut decoder = gif::DecodeOptions::new().read_info(RetryInterrupted(input))?;
+    let global_palette = decoder.global_palette().map(<[u8]>::to_vec);
+    let frame = decoder.read_next_frame()?.ok_or(Error::DimensionsError)?;
+    let colors = frame
+        .palette
+        .as_ref()
+        .or(global_palette.as_ref())
+        .ok_or(Error::PaletteError)?;
+    let palette = Palette::new(colors, frame.buffer.iter().max().copied().unwrap_or(0))?;
+    let (width, height) = (usize::from(frame.width), usize::from(frame.height));
+    if width == 0 || height == 0 {
+        return Err(Error::DimensionsError);
+    }
+    let columns = columns.clamp(1, width);
+    let rows = (height * columns).div_ceil(2 * width).min(height);
+
+    let mut thumbnail = String::with_capacity((columns + 1) * rows);
+    for row in 0..rows {
+        let (top, bottom) = (row * height / rows, (row + 1) * height / rows);
+        for column in 0..columns {
+            let (left, right) = (column * width / columns, (column + 1) * width / columns);
+            // A large cell can add up to more than fits in 32 bits
+            let mut brightness: u64 = 0;
+            for y in top..bottom {
+                for x in left..right {
+                    let (r, g, b) = palette.color(frame.buffer[y * width + x]);
+                    // Perceived brightness, scaled by 1000
+                    brightness += 299 * u64::from(r) + 587 * u64::from(g) + 114 * u64::from(b);
+                }
+            }
+            let pixels = ((bottom - top) * (right - left)) as u64;
+            let brightness = (brightness / (pixels * 1000)) as usize;
+            thumbnail.push(char::from(
+                THUMBNAIL_RAMP[brightness * (THUMBNAIL_RAMP.len() - 1) / 255],
+            ));
+        }
+        thumbnail.push('\n');
+    }
+    Ok(thumbnail)
+}
+
+/// Decodes an Octocart, and returns the decoded JSON payload as a string.
+///
+/// The input is buffered internally, so it doesn't need to be wrapped in a
+/// [`BufReader`](std::io::BufReader), and it may deliver the GIF in arbitrarily small segments,
+/// like a chunked network stream. Reads that fail with [`ErrorKind::Interrupted`] are retried.
+/// Decoding stops at the GIF's trailer, so any junk appended after it is ignored.
+///
+/// Example
+/// ```no_run
+/// let file = std::fs::File::open("test_octocart.gif").unwrap();
+/// let payload: String = decart::decode_octocart(file).unwrap();
+/// ```
+/// You can deserialize this string as an [`OctoCart`]:
+/// ```no_run
+/// # let payload = "{\"tickrate\":7,\"maxSize\":3215,\"screenRotation\":0,\"fontStyle\":\"octo\",\"touchInputMode\":\"none\",\"fillColor\"#FFCC00\",\"fillColor2\":\"#FF6600\",\"blendColor\":\"#662200\",\"backgroundColor\"\"#996600\",\"buzzColor\":\"#FFAA00\",\"quietColor\":\"#000000\",\"shiftQuirks\":0,\"loadStoreQuirks\":0,\"jumpQuirks\":0,\"logicQuirks\":true,\"clipQuirks\":true,\"vBlankQuirks\":true}";
+/// # use std::str::FromStr;
+/// use decart::OctoCart;
+/// let cart: OctoCart = OctoCart::from_str(payload).unwrap();
+/// ```
+/// Note that you can also deserialize from a file directly with [`from_file`](crate::from_file):
+/// ```no_run
+/// use decart::*;
+/// let cart: OctoCart = from_file("test_octocart.gif").unwrap();
+/// ```
+/// # Errors
+///
+/// Returns `Err` if there is a GIF decoding error, if the payload is larger than
+/// [`DEFAULT_MAX_BYTES`], or if the payload isn't valid UTF-8.
+pub fn decode_octocart<R: Read>(input: R) -> Result<String, Error> {
+    decode_octocart_with_gif_options(input, gif::DecodeOptions::new())
+}
+
+/// Decodes an Octocart with the given [`DecodeOptions`], and returns the decoded JSON payload as a
+/// string.
+///
+/// Example
+/// ```no_run
+/// use decart::DecodeOptions;
+/// let file = std::fs::File::open("untrusted_octocart.gif").unwrap();
+/// let options = DecodeOptions {
+///     max_bytes: 64 * 1024,
+///     ..DecodeOptions::default()
+/// };
+/// let payload: String = decart::decode_octocart_with_options(file, &options).unwrap();
+/// ```
+/// # Errors
+///
+/// Returns `Err` if there is a GIF decoding error, if the payload is larger or has more frames than
+/// allowed by the options, or if the payload isn't valid UTF-8 and the options don't allow lossy
+/// decoding.
+pub fn decode_octocart_with_options<R: Read>(
+    input: R,
+    options: &DecodeOptions,
+) -> Result<String, Error> {
+    decode_payload(input, usize::MAX, options, gif::DecodeOptions::new())
+}
+
+/// Decodes an Octocart with a preconfigured GIF decoder, and returns the decoded JSON payload as a
+/// string.
+///
+/// This is for advanced users who need control over the underlying GIF decoder, for instance to
+/// limit the memory it may use for each frame. The color output mode may be either indexed or
+/// RGBA. Payloads are limited to [`DEFAULT_MAX_BYTES`].
+///
+/// Example
+/// ```no_run
+/// let file = std::fs::File::open("test_octocart.gif").unwrap();
+/// let mut options = gif::DecodeOptions::new();
+/// options.set_memory_limit(gif::MemoryLimit(1024 * 1024));
+/// let payload: String = decart::decode_octocart_with_gif_options(file, options).unwrap();
+/// ```
+/// # Errors
+///
+/// Returns `Err` if there is a GIF decoding error, including exceeding the GIF decoder's memory
+/// limit, if the payload is larger than [`DEFAULT_MAX_BYTES`], or if the payload isn't valid UTF-8.
+pub fn decode_octocart_with_gif_options<R: Read>(
+    input: R,
+    opts: gif::DecodeOptions,
+) -> Result<String, Error> {
+    decode_payload(input, usize::MAX, &DecodeOptions::default(), opts)
+}
+
+/// Decodes only the start of an Octocart's payload, and returns at most `max_bytes` of the JSON
+/// payload as a string.
+///
+/// Decoding stops as soon as enough bytes have been read, so this is cheaper than
+/// [`decode_octocart`] when you only want a preview of the payload. Note that the returned string
+/// will generally not be valid JSON. If the payload is cut off in the middle of a multi-byte UTF-8
+/// sequence, that character is left out, so the string may be a few bytes shorter than
+/// `max_bytes`. Any other invalid UTF-8 is replaced with U+FFFD REPLACEMENT CHARACTER, which can
+/// make the string longer.
+///
+/// Example
+/// ```no_run
+/// let file = std::fs::File::open("test_octocart.gif").unwrap();
+/// let preview: String = decart::decode_prefix(file, 20).unwrap();
+/// ```
+/// # Errors
+///
+/// Returns `Err` if there is a GIF decoding error.
+pub fn decode_prefix<R: Read>(input: R, max_bytes: usize) -> Result<String, Error> {
+    let options = DecodeOptions {
+        max_bytes: usize::MAX,
+        lossy_utf8: true,
+        ..DecodeOptions::default()
+    };
+    decode_payload(input, max_bytes, &options, gif::DecodeOptions::new())
+}
+
+/// Decodes at most `prefix` bytes of the payload with the given options.
+fn decode_payload<R: Read>(
+    input: R,
+    prefix: usize,
+    options: &DecodeOptions,
+    gif_options: gif::DecodeOptions,
+) -> Result<String, Error> {
+    let mut decoder = Decoder::with_gif_options(input, options, gif_options)?;
+    let mut payload = Vec::new();
+    while let Some(bytes) = decoder.read_frame(prefix)? {
+        payload.extend_from_slice(&bytes);
+    }
+    // Only a prefix cuts the payload on purpose; a GIF that ends early is invalid as usual
+    if prefix < decoder.payload_length.unwrap_or(0) as usize {
+        drop_cut_character(&mut payload);
+    }
+    text(payload, options.lossy_utf8)
+}
+
+/// Drops a multi-byte UTF-8 sequence at the end of a payload that was cut off in the middle,
+/// rather than leaving it to be replaced by a (longer) replacement character.
+fn drop_cut_character(payload: &mut Vec<u8>) {
+    // A character is at most 4 bytes, and every byte after its first is a continuation byte
+    let start = (payload.len().saturating_sub(3)..payload.len())
+        .rev()
+        .find(|&i| payload[i] & 0xC0 != 0x80);
+    if let Some(start) = start {
+        if std::str::from_utf8(&payload[start..]).is_err_and(|error| error.error_len().is_none()) {
+            payload.truncate(start);
+        }
+    }
+}
+
+/// Converts the decoded payload to a string, since it's UTF-8 encoded JSON.
+fn text(payload: Vec<u8>, lossy: bool) -> Result<String, Error> {
+    if lossy {
+        Ok(String::from_utf8_lossy(&payload).into_owned())
+    } else {
+        Ok(String::from_utf8(payload)?)
+    }
+}
+
+/// Decodes an Octocart using the given palette instead of the palettes embedded in the GIF, and
+/// returns the decoded JSON payload as a string.
+///
+/// This is an advanced recovery tool for damaged carts, whose embedded palettes are corrupt even
+/// though the intended palette is known; for instance, if it was shared with an undamaged cart
+/// made with the same tool. The palette is given as RGB (or RGBA) bytes, and replaces both the
+/// global palette and any local frame palettes. RGBA palettes are recognized by their alpha
+/// bytes, which must all be 0xFF (opaque) or 0x00 (transparent). Payloads are limited to
+/// [`DEFAULT_MAX_BYTES`], and any invalid UTF-8 in them is replaced with U+FFFD REPLACEMENT
+/// CHARACTER.
+///
+/// Example
+/// ```no_run
+/// let intact = std::fs::File::open("intact_octocart.gif").unwrap();
+/// let palette = gif::DecodeOptions::new()
+///     .read_info(intact)
+///     .unwrap()
+///     .global_palette()
+///     .unwrap()
+///     .to_vec();
+/// let damaged = std::fs::File::open("damaged_octocart.gif").unwrap();
+/// let payload: String = decart::decode_with_palette(damaged, &palette).unwrap();
+/// ```
+/// # Errors
+///
+/// Returns `Err` if there is a GIF decoding error, if the GIF has no global palette to replace,
+/// if the palette has fewer than [`MIN_PALETTE_SIZE`](crate::MIN_PALETTE_SIZE) colors or is too
+/// small for the colors used by the GIF, or if the payload is larger than [`DEFAULT_MAX_BYTES`].
+pub fn decode_with_palette<R: Read>(input: R, palette: &[u8]) -> Result<String, Error> {
+    // Fewer colors than this can't carry every nybble
+    if Palette::new(palette, 0)?.len() < MIN_PALETTE_SIZE {
+        return Err(Error::PaletteError);
+    }
+    let mut decoder = Decoder::new(input, &DecodeOptions::default())?;
+    decoder.global_palette = palette.to_vec();
+    decoder.palette_override = true;
+    let mut payload = Vec::new();
+    while let Some(bytes) = decoder.read_next_frame()? {
+        payload.extend_from_slice(&bytes);
+    }
+    text(payload, true)
+}
+
+/// Decodes an Octocart whose payload is stored with the given bit layout, and returns the decoded
+/// JSON payload as a string.
+///
+/// This is a recovery tool for carts made by buggy encoders; see [`BitLayout::RedOnly`]. Payloads
+/// are limited to [`DEFAULT_MAX_BYTES`], and any invalid UTF-8 in them is replaced with U+FFFD
+/// REPLACEMENT CHARACTER.
+///
+/// Example
+/// ```no_run
+/// let file = std::fs::File::open("red_only_octocart.gif").unwrap();
+/// let payload: String = decart::decode_with_layout(file, decart::BitLayout::RedOnly).unwrap();
+/// ```
+/// # Errors
+///
+/// Returns `Err` if there is a GIF decoding error, if the first frame is too small to hold the
+/// payload's length, or if the payload is larger than [`DEFAULT_MAX_BYTES`].
+pub fn decode_with_layout<R: Read>(input: R, layout: BitLayout) -> Result<String, Error> {
+    let mut decoder = Decoder::new(input, &DecodeOptions::default())?;
+    decoder.layout = layout;
+    let mut payload = Vec::new();
+    while let Some(bytes) = decoder.read_next_frame()? {
+        payload.extend_from_slice(&bytes);
+    }
+    text(payload, true)
+}
+
+/// Decodes an Octocart, and returns both the raw bytes of its payload and the parsed cart.
+///
+/// This is useful for tools that need the exact bytes of the payload, for instance to store them
+/// or compute a checksum, since re-serializing the cart won't necessarily give the same bytes.
+///
+/// Example
+/// ```no_run
+/// let file = std::fs::File::open("test_octocart.gif").unwrap();
+/// let (bytes, cart) = decart::decode_full(file).unwrap();
+/// ```
+/// # Errors
+///
+/// Returns `Err` if there is a GIF decoding error, if the payload is larger than
+/// [`DEFAULT_MAX_BYTES`], or if the payload isn't valid UTF-8 or can't be parsed.
+pub fn decode_full<R: Read>(input: R) -> Result<(Vec<u8>, OctoCart), Error> {
+    let mut decoder = Decoder::new(input, &DecodeOptions::default())?;
+    let mut payload = Vec::new();
+    while let Some(bytes) = decoder.read_next_frame()? {
+        payload.extend_from_slice(&bytes);
+    }
+    let payload = text(payload, false)?;
+    let cart = parse_payload(&payload)?;
+    Ok((payload.into_bytes(), cart))
+}
+
+/// Decodes only the options of an Octocart.
+///
+/// If the cart was encoded with [`EncodeOptions::separate_options`](crate::EncodeOptions), decoding
+/// stops after the frames that hold the options, without decoding the program. Other carts are
+/// decoded in full.
+///
+/// Example
+/// ```no_run
+/// let file = std::fs::File::open("test_octocart.gif").unwrap();
+/// let options: octopt::Options = decart::decode_options_only(file).unwrap();
+/// ```
+/// # Errors
+///
+/// Returns `Err` if there is a GIF decoding error, if the payload is larger than
+/// [`DEFAULT_MAX_BYTES`], or if the payload can't be parsed.
+pub fn decode_options_only<R: Read>(input: R) -> Result<Options, Error> {
+    let mut decoder = Decoder::new(input, &DecodeOptions::default())?;
+    let mut payload = Vec::new();
+    while let Some(bytes) = decoder.read_next_frame()? {
+        payload.extend_from_slice(&bytes);
+        // The options group ends with a comma and whitespace padding at the end of a frame
+        let options_group = std::str::from_utf8(&payload)
+            .ok()
+            .and_then(|payload| payload.strip_prefix("{\"options\":"))
+            .and_then(|payload| payload.trim_end().strip_suffix(','))
+            .and_then(|options| serde_json::from_str(options).ok());
+        if let Some(options) = options_group {
+            return Ok(options);
+        }
+    }
+    Ok(parse_payload(&text(payload, false)?)?.options)
+}
+
+/// Whether a frame is cover-only, meaning that it's decorative and doesn't carry any of the
+/// payload, which is marked by its first pixel being transparent.
+fn is_cover_frame(frame: &gif::Frame) -> bool {
+    let pixels = usize::from(frame.width) * usize::from(frame.height);
+    if pixels > 0 && frame.buffer.len() == 4 * pixels {
+        // Transparent pixels have no alpha when the GIF decoder outputs RGBA colors
+        frame.buffer[3] == 0
+    } else {
+        frame
+            .buffer
+            .first()
+            .is_some_and(|&pixel| frame.transparent == Some(pixel))
+    }
+}
+
+/// Decodes the payload bytes carried by a single frame of an Octocart, given its index among all
+/// of the GIF's frames, starting at 0. This is a diagnostic tool for inspecting how the payload is
+/// spread over the frames.
+///
+/// The input is rewound to its start first. The payload starts with the first frame, so all frames
+/// before `frame_index` are decoded too. Frames that don't carry any part of the payload, like
+/// empty or cover-only frames and those after the end of the payload, decode to no bytes. The 4
+/// bytes of the length header aren't included in the first frame's bytes.
+///
+/// Example
+/// ```no_run
+/// let file = std::fs::File::open("test_octocart.gif").unwrap();
+/// let bytes: Vec<u8> = decart::decode_frame(file, 1).unwrap();
+/// ```
+/// # Errors
+///
+/// Returns `Err` if seeking in the input fails, if there is a GIF decoding error, or if the
+/// payload is larger than [`DEFAULT_MAX_BYTES`].
+pub fn decode_frame<R: Read + Seek>(mut input: R, frame_index: usize) -> Result<Vec<u8>, Error> {
+    input.rewind()?;
+    let mut decoder = Decoder::new(input, &DecodeOptions::default())?;
+    while let Some(bytes) = decoder.read_next_frame()? {
+        match (decoder.frames_read - 1).cmp(&frame_index) {
+            Ordering::Less => continue,
+            Ordering::Equal => return Ok(bytes),
+            // The requested frame was empty, and skipped
+            Ordering::Greater => break,
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// A stateful Octocart decoder, which decodes the payload one frame at a time.
+///
+/// Decoding stops as soon as the number of bytes declared in the Octocart's header has been
+/// decoded, so any padding pixels or extra frames after the payload are never read. Cover-only
+/// frames are skipped; see [`EncodeOptions::cover_frames`](crate::EncodeOptions::cover_frames).
+///
+/// Example
+/// ```no_run
+/// use decart::{DecodeOptions, Decoder};
+/// let file = std::fs::File::open("test_octocart.gif").unwrap();
+/// let mut decoder = Decoder::new(file, &DecodeOptions::default()).unwrap();
+/// while let Some(bytes) = decoder.read_next_frame().unwrap() {
+///     println!("Decoded {} bytes", bytes.len());
+/// }
+/// assert_eq!(decoder.payload_length(), Some(decoder.bytes_decoded() as u32));
+/// ```
+pub struct Decoder<R: Read> {
+    decoder: gif::Decoder<RetryInterrupted<R>>,
+    global_palette: Vec<u8>,
+    /// Whether the global palette was supplied by the user, and overrides all local palettes
+    palette_override: bool,
+    layout: BitLayout,
+    max_bytes: usize,
+    max_frames: usize,
+    payload_length: Option<u32>,
+    bytes_decoded: usize,
+    /// The number of GIF frames read so far, including empty ones
+    frames_read: usize,
+}
+
+impl<R: Read> Decoder<R> {
+    /// Creates a decoder, reading the GIF's header from `input`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is a GIF decoding error, or if the GIF has no global palette.
+    pub fn new(input: R, options: &DecodeOptions) -> Result<Self, Error> {
+        Self::with_gif_options(input, options, gif::DecodeOptions::new())
+    }
+
+    /// Creates a decoder like [`Self::new`], but with a preconfigured GIF decoder; see
+    /// [`decode_octocart_with_gif_options`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is a GIF decoding error, or if the GIF has no global palette.
+    pub fn with_gif_options(
+        input: R,
+        options: &DecodeOptions,
+        gif_options: gif::DecodeOptions,
+    ) -> Result<Self, Error> {
+        let decoder = gif_options.read_info(RetryInterrupted(input))?;
+        let global_palette = decoder
+            .global_palette()
+            .ok_or(Error::PaletteError)?
+            .to_vec();
+        Ok(Self {
+            decoder,
+            global_palette,
+            palette_override: false,
+            layout: BitLayout::Octo,
+            max_bytes: options.max_bytes,
+            max_frames: options.max_frames,
+            payload_length: None,
+            bytes_decoded: 0,
+            frames_read: 0,
+        })
+    }
+
+    /// Decodes the next frame, and returns the payload bytes it carries. Returns `None` once the
+    /// whole payload has been decoded, or if the GIF runs out of frames before that.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is a GIF decoding error, if the first frame is too small to hold the
+    /// payload's length, or if the payload is larger or has more frames than allowed by the
+    /// options.
+    pub fn read_next_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        self.read_frame(usize::MAX)
+    }
+
+    /// The length of the payload in bytes, as declared in the Octocart's header, or `None` if the
+    /// first frame hasn't been decoded yet.
+    pub fn payload_length(&self) -> Option<u32> {
+        self.payload_length
+    }
+
+    /// The number of payload bytes decoded so far, not counting the header.
+    pub fn bytes_decoded(&self) -> usize {
+        self.bytes_decoded
+    }
+
+    /// Like [`Self::read_next_frame`], but stops after `prefix` bytes of the payload.
+    fn read_frame(&mut self, prefix: usize) -> Result<Option<Vec<u8>>, Error> {
+        let size = self.payload_length.map_or(usize::MAX, |size| size as usize);
+        if self.bytes_decoded >= size.min(prefix) {
+            return Ok(None);
+        }
+        // Placeholder frames with no pixels and cover-only frames are skipped
+        let frame = loop {
+            let frame = self.decoder.read_next_frame()?;
+            self.frames_read += usize::from(frame.is_some());
+            if self.frames_read > self.max_frames {
+                return Err(Error::TooManyFrames);
+            }
+            match frame {
+                Some(frame) if frame.buffer.is_empty() || is_cover_frame(frame) => continue,
+                Some(frame) => break frame,
+                None => return Ok(None),
+            }
+        };
+        let pixels = usize::from(frame.width) * usize::from(frame.height);
+        let layout = self.layout;
+        let units: Vec<u8> = if frame.buffer.len() == 4 * pixels {
+            // The GIF decoder was configured to output RGBA colors instead of palette indices
+            frame
+                .buffer
+                .chunks_exact(4)
+                .map(|rgba| layout.extract((rgba[0], rgba[1], rgba[2])))
+                .collect()
+        } else {
+            let colors = match &frame.palette {
+                Some(colors) if !self.palette_override => colors,
+                _ => &self.global_palette,
+            };
+            let palette = Palette::new(colors, frame.buffer.iter().max().copied().unwrap_or(0))?;
+            frame
+                .buffer
+                .iter()
+                .map(|&pixel| layout.extract(palette.color(pixel)))
+                .collect()
+        };
+        let pixels_per_byte = 8 / layout.bits();
+        let byte = |pixels: &[u8]| {
+            pixels
+                .iter()
+                .fold(0, |byte, &bits| (byte << layout.bits()) | bits)
+        };
+        // The 4 header bytes occupy the first pixels of the first frame, 8 in the Octo layout
+        let start = if self.payload_length.is_none() {
+            if units.len() < 4 * pixels_per_byte {
+                return Err(Error::DimensionsError);
+            }
+            let size = units[..4 * pixels_per_byte]
+                .chunks_exact(pixels_per_byte)
+                .fold(0, |size, pixels| (size << 8) | u32::from(byte(pixels)));
+            if usize::try_from(size).map_or(true, |size| size > self.max_bytes) {
+                return Err(Error::PayloadTooLarge);
+            }
+            self.payload_length = Some(size);
+            4 * pixels_per_byte
+        } else {
+            0
+        };
+        let remaining =
+            (self.payload_length.unwrap_or(0) as usize).min(prefix) - self.bytes_decoded;
+        // Pixels at the end of a frame that don't make up a whole byte don't carry any data
+        let bytes: Vec<u8> = units[start..]
+            .chunks_exact(pixels_per_byte)
+            .take(remaining)
+            .map(byte)
+            .collect();
+        self.bytes_decoded += bytes.len();
+        Ok(Some(bytes))
+    }
+}
+
+/// Retries reads that were interrupted, which the `gif` crate's internal buffering doesn't.
+struct RetryInterrupted<R>(R);
+
+impl<R: Read> Read for RetryInterrupted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            match self.0.read(buf) {
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A color palette with either 3 (RGB) or 4 (RGBA) bytes per color.
+struct Palette<'a> {
+    colors: &'a [u8],
+    stride: usize,
+}
+
+impl<'a> Palette<'a> {
+    /// Detects the palette's stride, given the highest color index used by a frame. GIF palettes
+    /// are RGB, so that's preferred whenever it fits, unless every fourth byte looks like an
+    /// alpha channel: fully opaque or transparent, with at least one opaque color.
+    fn new(colors: &'a [u8], max_index: u8) -> Result<Self, Error> {
+        let entries = usize::from(max_index) + 1;
+        let fits =
+            |stride: usize| colors.len().is_multiple_of(stride) && colors.len() / stride >= entries;
+        let alpha = colors.chunks_exact(4).map(|rgba| rgba[3]);
+        let has_alpha = alpha.clone().all(|alpha| alpha == 0 || alpha == 0xFF)
+            && alpha.clone().any(|alpha| alpha == 0xFF);
+        let stride = if fits(4) && (has_alpha || !fits(3)) {
+            4
+        } else if fits(3) {
+            3
+        } else {
+            return Err(Error::PaletteError);
+        };
+        Ok(Self { colors, stride })
+    }
+
+    /// The number of colors in the palette.
+    fn len(&self) -> usize {
+        self.colors.len() / self.stride
+    }
+
+    fn color(&self, pixel: u8) -> (u8, u8, u8) {
+        let i = usize::from(pixel) * self.stride;
+        (self.colors[i], self.colors[i + 1], self.colors[i + 2])
+    }
+}
+
+fn nybble((r, g, b): (u8, u8, u8)) -> u8 {
+    ((r << 3) & 8) | ((g << 1) & 6) | b & 1
+}
+
+/// Reads the 32-bit payload length from the first 8 pixels of the first frame.
+fn header(buffer: &[u8], palette: &Palette) -> u32 {
+    ((u32::from(byte(buffer, palette, 0))) << 24)
+        | ((u32::from(byte(buffer, palette, 2))) << 16)
+        | ((u32::from(byte(buffer, palette, 4))) << 8)
+        | u32::from(byte(buffer, palette, 6))
+}
+
+fn byte(buffer: &[u8], palette: &Palette, i: usize) -> u8 {
+    (nybble(palette.color(buffer[i])) << 4) | nybble(palette.color(buffer[i + 1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgba_palette() {
+        // Four colors with the nybbles 0x0, 0x5, 0xA and 0xF encoded in their LSBs
+        let rgb = [0, 0, 0, 0, 2, 1, 1, 1, 0, 1, 3, 1];
+        let rgba = [0, 0, 0, 255, 0, 2, 1, 255, 1, 1, 0, 255, 1, 3, 1, 255];
+        let buffer = [1, 2, 3, 0];
+        for colors in [&rgb[..], &rgba[..]] {
+            let palette = Palette::new(colors, 3).unwrap();
+            assert_eq!(byte(&buffer, &palette, 0), 0x5A);
+            assert_eq!(byte(&buffer, &palette, 2), 0xF0);
+        }
+        assert_eq!(Palette::new(&rgb, 3).unwrap().stride, 3);
+        assert_eq!(Palette::new(&rgba, 3).unwrap().stride, 4);
+
+        // An RGB palette that happens to be divisible by 4 is still treated as RGB
+        assert_eq!(Palette::new(&[0; 48], 15).unwrap().stride, 3);
+
+        // An RGBA palette that happens to be divisible by 3 is recognized by its alpha channel
+        let rgba: Vec<u8> = rgba.iter().cycle().take(4 * 48).copied().collect();
+        assert_eq!(Palette::new(&rgba, 15).unwrap().stride, 4);
+
+        // Pixels that index past the end of the palette are an error, not a panic
+        assert!(Palette::new(&rgb, 4).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+//! Encoding of Octocarts.
+
+use crate::{Error, OctoCart};
+use octopt::color::Color;
+use std::io::Write;
+
+/// The smallest number of colors a palette can have and still carry any payload.
+///
+/// Each pixel carries a nybble in the least significant bits of its color, so a pair of pixels can
+/// only carry any byte if there is a color for each of the 16 possible nybbles. A monochrome
+/// palette, for instance, can only carry the two nybbles of its colors; this can't be worked around
+/// with a cleverer palette, since a pixel's color is all the information it has. Encoded carts
+/// therefore always use exactly 16 colors, unless [`EncodeOptions::visible_data`] adds 16 more.
+pub const MIN_PALETTE_SIZE: usize = 16;
+
+/// The number of pixels taken up by the 4-byte payload length at the start of the first frame.
+const HEADER_PIXELS: usize = 8;
+
+/// Options for how to lay out the payload when encoding an Octocart.
+#[derive(Debug, Default)]
+pub struct EncodeOptions {
+    /// The width and height of each frame, in order. Frames don't need to have the same size; the
+    /// last entry is reused for as many additional frames as the payload needs. If empty, the
+    /// smallest square-ish frame that fits the entire payload is used; see
+    /// [`OctoCart::min_carrier_dimensions`].
+    pub frame_dimensions: Vec<(u16, u16)>,
+    /// Whether the pixels that carry the payload should be visibly distinct from the rest of the
+    /// image, rather than hidden in the least significant bits of the cart's background color.
+    ///
+    /// This is useful for debugging, as each nybble of the payload is drawn as a distinct shade of
+    /// grey, making it easy to see where the data is. The payload decodes the same either way.
+    pub visible_data: bool,
+    /// The exact number of frames to spread the payload over, for instance to match the frame
+    /// count of an existing cart (see [`cart_info`](crate::cart_info)). Frames that aren't needed
+    /// for the payload are filled with padding. If [`frame_dimensions`](Self::frame_dimensions)
+    /// is empty, the frames are sized to fit the payload evenly, but never smaller than the
+    /// first frame needs to be for the payload length.
+    pub target_frames: Option<usize>,
+    /// How many times viewers should play the frames of the GIF, which is written as a Netscape
+    /// looping extension. The default of 0 loops forever.
+    pub loop_count: u16,
+    /// Whether to put the options and the program in separate groups of frames, so that the
+    /// options can be read quickly with [`decode_options_only`](crate::decode_options_only)
+    /// without decoding the program.
+    ///
+    /// The payload is then a JSON object with the options first, followed by whitespace padding
+    /// that fills up the options' last frame, and then the program starting in a new frame. Since
+    /// it's still valid JSON, such carts can be decoded by Octo and other tools as usual.
+    pub separate_options: bool,
+    /// The size in bytes that the GIF should be padded to, for instance to give every cart in an
+    /// archive the same size. Inert frames with the cart's background color are added after the
+    /// payload until the GIF is at least this large, so it can overshoot by up to one compressed
+    /// frame. If the GIF is already this large, no padding is added. Decoders ignore the padding,
+    /// since they stop at the end of the payload.
+    pub target_size: Option<usize>,
+    /// Decorative frames that don't carry any of the payload, as `(index, frame)`, where `index`
+    /// is the frame's position among all of the GIF's frames. This lets the cover art be animated
+    /// with frames interspersed among those that carry the payload.
+    ///
+    /// By convention, a frame whose first pixel is transparent is cover-only, and is skipped when
+    /// decoding. Each of these frames must therefore have a transparent color, which its first
+    /// pixel is set to. Note that Octo itself doesn't follow this convention, and reads every
+    /// frame as payload.
+    pub cover_frames: Vec<(usize, gif::Frame<'static>)>,
+}
+
+/// A writer that keeps track of how many bytes have been written to it.
+struct CountingWriter<W> {
+    inner: W,
+    count: usize,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Returns the smallest square-ish `(width, height)` with at least the given (even) number of
+/// pixels.
+pub(crate) fn square_dimensions(pixels: usize) -> (u32, u32) {
+    let pixels = (pixels + (pixels & 1)).max(2) as u32;
+    let width = f64::from(pixels).sqrt().ceil() as u32;
+    (width, pixels.div_ceil(width))
+}
+
+/// Returns the color with the nybble `n` stored in its least significant bits, 1 in the red and
+/// blue channels and 2 in the green channel.
+fn with_nybble(color: &Color, n: u8) -> [u8; 3] {
+    [
+        (color.r & !1) | ((n >> 3) & 1),
+        (color.g & !3) | ((n >> 1) & 3),
+        (color.b & !1) | (n & 1),
+    ]
+}
+
+/// Encodes an Octocart, writing it as a GIF to `output`.
+///
+/// The payload is stored in two pixels per byte, using as many frames as necessary. Unused pixels
+/// at the end of the last frame are padding, which has the cart's background color, as are any
+/// frames added to reach [`EncodeOptions::target_size`]. The GIF's logical screen is large enough
+/// to fit the largest frame.
+///
+/// Example
+/// ```no_run
+/// use decart::*;
+/// let cart: OctoCart = from_file("test_octocart.gif").unwrap();
+/// let file = std::fs::File::create("copy.gif").unwrap();
+/// encode_octocart(file, &cart, &EncodeOptions::default()).unwrap();
+/// ```
+/// # Errors
+///
+/// Returns `Err` if the frame dimensions are empty or can't fit the payload in the target number
+/// of frames, if the first frame has fewer than the 8 pixels needed for the payload length, if a
+/// cover frame has no transparent color, or if there is a GIF encoding error.
+pub fn encode_octocart<W: Write>(
+    output: W,
+    cart: &OctoCart,
+    options: &EncodeOptions,
+) -> Result<(), Error> {
+    let auto_dimensions = |pixels: usize| {
+        if options.frame_dimensions.is_empty() {
+            let (width, height) = square_dimensions(pixels);
+            vec![(
+                u16::try_from(width).unwrap_or(u16::MAX),
+                u16::try_from(height).unwrap_or(u16::MAX),
+            )]
+        } else {
+            options.frame_dimensions.clone()
+        }
+    };
+    let (payload, frame_dimensions) = if options.separate_options {
+        let options_group = format!(
+            "{{\"options\":{},",
+            serde_json::to_string(&cart.options).expect("Options can always be serialized as JSON")
+        );
+        // The program and any other members of the cart follow the options
+        let mut members =
+            serde_json::to_value(cart).expect("Carts can always be serialized as JSON");
+        if let Some(members) = members.as_object_mut() {
+            members.remove("options");
+        }
+        let members = members.to_string();
+        let program_group = &members[1..];
+        let frame_dimensions =
+            auto_dimensions(2 * (4 + options_group.len()).max(program_group.len()));
+        // Pad the options with whitespace to the end of the frames that hold them, including the
+        // 4 header bytes, so that the program starts in a frame of its own
+        let mut group_length = 0;
+        for frame in 0.. {
+            if group_length >= 4 + options_group.len() {
+                break;
+            }
+            let (width, height) = frame_dimensions[frame.min(frame_dimensions.len() - 1)];
+            let capacity = (usize::from(width) * usize::from(height)) / 2;
+            if capacity == 0 {
+                return Err(Error::DimensionsError);
+            }
+            group_length += capacity;
+        }
+        let padding = " ".repeat(group_length - 4 - options_group.len());
+        (
+            format!("{}{}{}", options_group, padding, program_group),
+            frame_dimensions,
+        )
+    } else {
+        let payload = cart.to_string();
+        let frames = options.target_frames.unwrap_or(1).max(1);
+        // Spreading a short payload over many frames mustn't leave the header without room
+        let frame_dimensions =
+            auto_dimensions(((2 * (4 + payload.len())).div_ceil(frames)).max(HEADER_PIXELS));
+        (payload, frame_dimensions)
+    };
+    let mut bytes = u32::try_from(payload.len())
+        .map_err(|_| Error::PayloadTooLarge)?
+        .to_be_bytes()
+        .to_vec();
+    bytes.extend_from_slice(payload.as_bytes());
+
+    // Lay out the frames until there's room for the whole payload, or until the target number of
+    // frames is reached. An odd pixel at the end of a frame can't carry half a byte.
+    let mut frames = Vec::new();
+    let mut remaining = 2 * bytes.len();
+    while remaining > 0 || frames.len() < options.target_frames.unwrap_or(0) {
+        let (width, height) = frame_dimensions[frames.len().min(frame_dimensions.len() - 1)];
+        let capacity = (usize::from(width) * usize::from(height)) & !1;
+        // Decoders need the whole payload length in the first frame
+        if capacity == 0 || (frames.is_empty() && capacity < HEADER_PIXELS) {
+            return Err(Error::DimensionsError);
+        }
+        frames.push((width, height, capacity));
+        remaining = remaining.saturating_sub(capacity);
+    }
+    if options
+        .target_frames
+        .is_some_and(|target| frames.len() > target)
+    {
+        return Err(Error::DimensionsError);
+    }
+    let mut cover_frames = Vec::new();
+    for (index, frame) in &options.cover_frames {
+        let mut frame = frame.clone();
+        let transparent = frame.transparent.ok_or(Error::CoverFrameError)?;
+        if let Some(pixel) = frame.buffer.to_mut().first_mut() {
+            *pixel = transparent;
+        }
+        cover_frames.push((*index, frame));
+    }
+    cover_frames.sort_by_key(|&(index, _)| index);
+    let dimensions = frames
+        .iter()
+        .map(|&(width, height, _)| (width, height))
+        .chain(
+            cover_frames
+                .iter()
+                .map(|(_, frame)| (frame.width, frame.height)),
+        );
+    let screen_width = dimensions
+        .clone()
+        .map(|(width, _)| width)
+        .max()
+        .unwrap_or(0);
+    let screen_height = dimensions.map(|(_, height)| height).max().unwrap_or(0);
+
+    // The first 16 palette entries are the background color with each possible nybble, followed
+    // by 16 shades of grey with the same nybbles for visible data
+    let background = cart
+        .options
+        .colors
+        .background_color
+        .as_ref()
+        .unwrap_or(&Color { r: 0, g: 0, b: 0 });
+    let mut palette: Vec<u8> = (0..MIN_PALETTE_SIZE as u8)
+        .flat_map(|n| with_nybble(background, n))
+        .collect();
+    let data_offset = if options.visible_data {
+        palette.extend((0..16).flat_map(|n| {
+            let grey = n * 0x11;
+            with_nybble(
+                &Color {
+                    r: grey,
+                    g: grey,
+                    b: grey,
+                },
+                n,
+            )
+        }));
+        16
+    } else {
+        0
+    };
+
+    let pixels: Vec<u8> = bytes
+        .iter()
+        .flat_map(|byte| [data_offset + (byte >> 4), data_offset + (byte & 0xF)])
+        .collect();
+
+    let output = CountingWriter {
+        inner: output,
+        count: 0,
+    };
+    let mut encoder = gif::Encoder::new(output, screen_width, screen_height, &palette)?;
+    encoder.set_repeat(match options.loop_count {
+        0 => gif::Repeat::Infinite,
+        count => gif::Repeat::Finite(count),
+    })?;
+    let mut pixels = pixels.as_slice();
+    let (mut last_width, mut last_height) = (0, 0);
+    let mut cover_frames = cover_frames.into_iter().peekable();
+    let mut frame_count = 0;
+    for (width, height, capacity) in frames {
+        while let Some((_, cover)) = cover_frames.next_if(|&(index, _)| index <= frame_count) {
+            encoder.write_frame(&cover)?;
+            frame_count += 1;
+        }
+        frame_count += 1;
+        let (data, rest) = pixels.split_at(capacity.min(pixels.len()));
+        pixels = rest;
+        let mut buffer = data.to_vec();
+        buffer.resize(usize::from(width) * usize::from(height), 0);
+        let frame = gif::Frame::from_indexed_pixels(width, height, &buffer, None);
+        encoder.write_frame(&frame)?;
+        (last_width, last_height) = (width, height);
+    }
+    for (_, cover) in cover_frames {
+        encoder.write_frame(&cover)?;
+    }
+    // Pad with frames the size of the last one, leaving room for the 1-byte trailer
+    if let Some(target_size) = options.target_size {
+        let buffer = vec![0; usize::from(last_width) * usize::from(last_height)];
+        let padding = gif::Frame::from_indexed_pixels(last_width, last_height, &buffer, None);
+        while encoder.get_ref().count + 1 < target_size {
+            encoder.write_frame(&padding)?;
+        }
+    }
+    encoder.into_inner()?;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+//! Best-effort recovery of malformed JSON payloads, used when decoding leniently.
+
+use crate::OctoCart;
+
+/// Scans JSON text and returns the characters needed to close any open strings, objects and
+/// arrays, the positions of all commas outside of strings, and whether the text ends in the middle
+/// of an escape sequence.
+fn scan(json: &str) -> (String, Vec<usize>, bool) {
+    let mut stack = Vec::new();
+    let mut commas = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in json.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => stack.push('}'),
+            '[' => stack.push(']'),
+            '}' | ']' => {
+                stack.pop();
+            }
+            ',' => commas.push(i),
+            _ => (),
+        }
+    }
+
+    let mut closers = String::new();
+    if in_string {
+        closers.push('"');
+    }
+    closers.extend(stack.iter().rev());
+    (closers, commas, escaped)
+}
+
+/// Removes control characters between JSON tokens, other than the whitespace allowed by JSON, and
+/// returns the remaining text along with the number of characters removed. Control characters
+/// inside strings are left alone.
+pub(crate) fn strip_control_characters(json: &str) -> (String, usize) {
+    let mut stripped = String::with_capacity(json.len());
+    let mut removed = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in json.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+        } else if c.is_ascii_control() && !matches!(c, '\t' | '\n' | '\r') {
+            removed += 1;
+            continue;
+        } else if c == '"' {
+            in_string = true;
+        }
+        stripped.push(c);
+    }
+    (stripped, removed)
+}
+
+/// Attempts to recover a payload that was cut short before its closing brace, by appending the
+/// minimal closing quotes and braces. If that isn't enough to get a valid cart, incomplete members
+/// are dropped one at a time from the end.
+///
+/// Returns the recovered cart along with a description of what was done.
+pub(crate) fn recover_truncated(payload: &str) -> Option<(OctoCart, String)> {
+    let (_, commas, _) = scan(payload);
+    let mut end = payload.len();
+    let mut dropped = 0;
+    loop {
+        let mut json = payload[..end].to_string();
+        // Drop an escape sequence that was cut off in the middle
+        if let (_, _, true) = scan(&json) {
+            json.pop();
+        }
+        let (closers, _, _) = scan(&json);
+        if !closers.starts_with('"') {
+            json.truncate(json.trim_end().trim_end_matches(',').len());
+        }
+        json.push_str(&closers);
+        if let Ok(cart) = serde_json::from_str(&json) {
+            let mut description = format!("appended `{}` to truncated payload", closers);
+            if dropped > 0 {
+                description.push_str(&format!(" after dropping {} incomplete member(s)", dropped));
+            }
+            return Some((cart, description));
+        }
+        end = *commas.iter().rev().find(|&&comma| comma < end)?;
+        dropped += 1;
+    }
+}
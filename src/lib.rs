@@ -11,7 +11,8 @@
 //!   [`deca`](https://crates.io/crates/deca), or saved as JSON for the [CHIP-8
 //!   Archive](https://github.com/JohnEarnest/chip8Archive), as an `.octo.rc` file for C-Octo or
 //!   [`termin-8`](https://crates.io/crates/termin-8), etc.
-//! * Encoding: TODO
+//! * Encoding: You can embed a program and its runtime settings in a new Octocart with
+//!   [`encode_octocart`], in order to share it or submit it to the CHIP-8 Archive.
 //!
 //! Octo cartridge files are GIF89a images with a payload steganographically
 //! embedded in one or more animation frames. Data is stored in the least significant
@@ -19,7 +20,9 @@
 //! allowing us to pack a hidden byte into every 2 successive pixels.
 //!
 //! The payload consists of a 32-bit length, followed by a sequence of ASCII bytes
-//! consisting of the JSON-encoded options dictionary and source text.
+//! consisting of the JSON-encoded options dictionary and source text. Animated carts
+//! can also have decorative "cover-only" frames that don't carry any of the payload;
+//! by convention, these are marked by a transparent first pixel.
 //!
 //! An Octo cartridge contains the source code of an Octo program, and a set of
 //! options for the Octo runtime on how to run the program.
@@ -28,26 +31,60 @@
 //! * To interpret an assembled program, check out the [`deca`](https://crates.io/crates/deca) crate (backend) or
 //!   a program like [`termin-8`](https://crates.io/crates/termin-8) (frontend and graphics).
 
-use octopt::Options;
+mod decode;
+mod encode;
+mod lenient;
+mod program;
+
+pub use decode::{
+    cart_info, decode_frame, decode_full, decode_octocart, decode_octocart_with_gif_options,
+    decode_octocart_with_options, decode_options_only, decode_prefix, decode_with_layout,
+    decode_with_palette, thumbnail_ascii, BitLayout, CartInfo, DecodeOptions, Decoder,
+    DEFAULT_MAX_BYTES, DEFAULT_MAX_FRAMES,
+};
+pub use encode::{encode_octocart, EncodeOptions, MIN_PALETTE_SIZE};
+
+use octopt::color::Color;
+use octopt::{Colors, Font, Options, Platform, Quirks};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fs::File;
-use std::io::Read;
 use std::path::Path;
 use std::str::FromStr;
-use std::u8;
 use thiserror::Error;
 
 /// Representation of the payload in the Octo cartridge.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct OctoCart {
     /// The source code of the `.8o` file used to generated the Octocart, as a string of ASCII characters
     pub program: String,
     /// Representation of the Octo runtime settings required to run this program correctly
     pub options: Options,
+    /// The title of the program, as in the CHIP-8 Archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The author of the program, as in the CHIP-8 Archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+}
+
+/// The problems found in an Octocart by [`OctoCart::validate_all`].
+#[derive(Debug, Default, PartialEq)]
+pub struct DecodeReport {
+    /// Problems that keep the cart from working or from being distributed as it is
+    pub errors: Vec<String>,
+    /// Settings that are valid, but probably not what the author intended
+    pub warnings: Vec<String>,
+}
+
+impl DecodeReport {
+    /// Whether no problems at all were found.
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty() && self.warnings.is_empty()
+    }
 }
 
-/// Represents the types of errors that can occur during decoding of an Octocart.
+/// Represents the types of errors that can occur during decoding or encoding of an Octocart.
 #[derive(Error, Debug)]
 pub enum Error {
     /// IO error while reading Octocart file
@@ -62,6 +99,813 @@ pub enum Error {
     /// Palette error
     #[error("Failed to parse palette")]
     PaletteError,
+    /// Encoding error while writing Octocart
+    #[error("Failed to encode file")]
+    EncodingError(#[from] gif::EncodingError),
+    /// The payload is too large for an Octocart
+    #[error("Payload is too large")]
+    PayloadTooLarge,
+    /// The requested frame dimensions can't hold the payload
+    #[error("Invalid frame dimensions")]
+    DimensionsError,
+    /// An encoded Octocart didn't decode to the original cart
+    #[error("Round-trip mismatch: {0}")]
+    RoundtripError(String),
+    /// Unknown color scheme name
+    #[error("Unknown color scheme: {0}")]
+    ColorSchemeError(String),
+    /// An option has an invalid value, given as the option's key, the value, and the values the
+    /// option allows
+    #[error("Invalid value {1} for {0}, expected one of: {}", .2.join(", "))]
+    ValidationError(String, String, &'static [&'static str]),
+    /// The payload isn't valid UTF-8
+    #[error("Payload is not valid UTF-8")]
+    Utf8Error(#[from] std::string::FromUtf8Error),
+    /// The Octocart has more frames than allowed
+    #[error("Too many frames")]
+    TooManyFrames,
+    /// A cover-only frame has no transparent color to mark it with
+    #[error("Cover frame has no transparent color")]
+    CoverFrameError,
+}
+
+/// Options that only allow a fixed set of values, as `(key, values)`.
+const ENUM_OPTIONS: [(&str, &[&str]); 3] = [
+    (
+        "fontStyle",
+        &[
+            "octo",
+            "vip",
+            "dream6800",
+            "eti660",
+            "schip",
+            "fish",
+            "akouz1",
+        ],
+    ),
+    (
+        "touchInputMode",
+        &["none", "swipe", "seg16", "seg16fill", "gamepad", "vip"],
+    ),
+    ("screenRotation", &["0", "90", "180", "270"]),
+];
+
+/// Finds an option in a JSON object of options whose value isn't one of the values it allows.
+fn invalid_enum_option(options: &serde_json::Value) -> Option<Error> {
+    ENUM_OPTIONS.iter().find_map(|(key, allowed)| {
+        let value = match options.get(key)? {
+            serde_json::Value::Null => return None,
+            serde_json::Value::String(string) => string.clone(),
+            value => value.to_string(),
+        };
+        if allowed.contains(&value.as_str()) {
+            None
+        } else {
+            Some(Error::ValidationError(key.to_string(), value, allowed))
+        }
+    })
+}
+
+/// Parses a payload like [`OctoCart::from_str`], but reports invalid values of options that only
+/// allow a fixed set of values as a [`Error::ValidationError`].
+pub(crate) fn parse_payload(payload: &str) -> Result<OctoCart, Error> {
+    OctoCart::from_str(payload).map_err(|error| {
+        serde_json::from_str::<serde_json::Value>(payload)
+            .ok()
+            .and_then(|json| invalid_enum_option(&json["options"]))
+            .unwrap_or(Error::ParsingError(error))
+    })
+}
+
+/// Copies options, which can't be cloned directly, by round-tripping them through JSON.
+fn clone_options(options: &Options) -> Options {
+    let json = serde_json::to_string(options).expect("Options can always be serialized as JSON");
+    serde_json::from_str(&json).expect("Options can always be deserialized from their own JSON")
+}
+
+/// Named color schemes, as `(name, [fill, fill2, blend, background, buzz, quiet])`.
+const COLOR_SCHEMES: [(&str, [u32; 6]); 6] = [
+    (
+        "octo",
+        [0xFFCC00, 0xFF6600, 0x662200, 0x996600, 0xFFAA00, 0x000000],
+    ),
+    (
+        "lcd",
+        [0x0C1218, 0x264D26, 0xD1DB8C, 0x6A8A5A, 0xD1DB8C, 0x0C1218],
+    ),
+    (
+        "hotdog",
+        [0xFF0000, 0xFFFF00, 0xFFFFFF, 0x000000, 0x990000, 0x330000],
+    ),
+    (
+        "greyscale",
+        [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000, 0xAAAAAA, 0x000000],
+    ),
+    (
+        "gameboy",
+        [0x0F380F, 0x306230, 0x8BAC0F, 0x9BBC0F, 0x306230, 0x9BBC0F],
+    ),
+    (
+        "cga",
+        [0x55FFFF, 0xFF55FF, 0xFFFFFF, 0x000000, 0xFF55FF, 0x000000],
+    ),
+];
+
+fn color(rgb: u32) -> Option<Color> {
+    Some(Color {
+        r: (rgb >> 16) as u8,
+        g: (rgb >> 8) as u8,
+        b: rgb as u8,
+    })
+}
+
+impl OctoCart {
+    /// Sets all six color options at once from a named color scheme.
+    ///
+    /// Available color schemes are `octo` (Octo's default yellow/brown colors), `lcd`, `hotdog`,
+    /// `greyscale`, `gameboy` (the four shades of green of the original Game Boy) and `cga`
+    /// (cyan/magenta/white on black).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is no color scheme with the given name.
+    pub fn set_color_scheme(&mut self, name: &str) -> Result<(), Error> {
+        let (_, [fill, fill2, blend, background, buzz, quiet]) = COLOR_SCHEMES
+            .iter()
+            .find(|(scheme, _)| *scheme == name)
+            .ok_or_else(|| Error::ColorSchemeError(name.to_string()))?;
+        self.options.colors = Colors {
+            fill_color: color(*fill),
+            fill_color2: color(*fill2),
+            blend_color: color(*blend),
+            background_color: color(*background),
+            buzz_color: color(*buzz),
+            quiet_color: color(*quiet),
+        };
+        Ok(())
+    }
+
+    /// Returns the smallest square-ish frame size, as `(width, height)`, that can fit this cart's
+    /// entire payload in a single frame.
+    pub fn min_carrier_dimensions(&self) -> (u32, u32) {
+        // Every byte takes up two pixels, including the 4 bytes of the length header
+        encode::square_dimensions(2 * (4 + self.to_string().len()))
+    }
+
+    /// Returns the options as a generic JSON value, with the same keys as in the payload, so
+    /// individual options can be inspected or manipulated without knowing their types.
+    pub fn options_json_value(&self) -> serde_json::Value {
+        serde_json::to_value(&self.options).expect("Options can always be serialized as JSON")
+    }
+
+    /// Checks the cart for options that are valid, but probably not what the author intended, and
+    /// returns a warning for each problem found.
+    ///
+    /// Currently, this warns if `fillColor` is the same as `backgroundColor`, which makes graphics
+    /// invisible, or if `fillColor2` is the same as `backgroundColor` and the program uses
+    /// XO-CHIP's `plane` instruction to draw in the second plane.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r##"{"program":"","options":{"fillColor":"#000000","backgroundColor":"#000000"}}"##).unwrap();
+    /// assert_eq!(cart.validate().len(), 1);
+    /// ```
+    pub fn validate(&self) -> Vec<String> {
+        let colors = &self.options.colors;
+        let mut warnings = Vec::new();
+        let invisible = |color: &Option<Color>| {
+            color.is_some()
+                && colors.background_color.is_some()
+                && *color == colors.background_color
+        };
+        if invisible(&colors.fill_color) {
+            warnings.push(
+                "fillColor is the same as backgroundColor, so graphics are invisible".to_string(),
+            );
+        }
+        if invisible(&colors.fill_color2) && program::uses_planes(&self.program) {
+            warnings.push(
+                "fillColor2 is the same as backgroundColor, so graphics in the second plane are invisible"
+                    .to_string(),
+            );
+        }
+        warnings
+    }
+
+    /// Returns the memory address the program should be loaded at and start executing from, as
+    /// given by the `startAddress` option, or the standard `0x200` if the cart doesn't specify one.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r#"{"program":"","options":{"startAddress":1536}}"#).unwrap();
+    /// assert_eq!(cart.start_address(), 0x600);
+    /// ```
+    pub fn start_address(&self) -> u16 {
+        self.options.start_address.unwrap_or(0x200)
+    }
+
+    /// Lists the quirks whose settings in this cart differ from the canonical settings of the
+    /// given platform, as `(quirk, cart, platform)`, where `quirk` is the quirk's option key.
+    ///
+    /// A quirk that isn't set is treated as disabled, since that's the default behavior. The
+    /// `loresDXY0Quirks` setting isn't a simple on/off switch, so it's not compared.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r#"{"program":"","options":{"shiftQuirks":true,"resClearQuirks":true}}"#).unwrap();
+    /// assert_eq!(
+    ///     cart.quirks_vs_platform(octopt::Platform::Octo),
+    ///     vec![("shiftQuirks".to_string(), true, false)]
+    /// );
+    /// ```
+    pub fn quirks_vs_platform(&self, platform: Platform) -> Vec<(String, bool, bool)> {
+        self.quirks_vs(&Options::new(platform))
+    }
+
+    /// Lists the quirks that differ from the given options, like [`Self::quirks_vs_platform`].
+    fn quirks_vs(&self, options: &Options) -> Vec<(String, bool, bool)> {
+        fn quirks(quirks: &Quirks) -> [(&'static str, Option<bool>); 13] {
+            [
+                ("shiftQuirks", quirks.shift),
+                ("loadStoreQuirks", quirks.load_store),
+                ("jumpQuirks", quirks.jump0),
+                ("logicQuirks", quirks.logic),
+                ("clipQuirks", quirks.clip),
+                ("vBlankQuirks", quirks.vblank),
+                ("vfOrderQuirks", quirks.vf_order),
+                ("resClearQuirks", quirks.res_clear),
+                ("delayWrapQuirks", quirks.delay_wrap),
+                ("hiresCollisionQuirks", quirks.hires_collision),
+                ("clipCollisionQuirks", quirks.clip_collision),
+                ("scrollQuirks", quirks.scroll),
+                ("overflowIQuirks", quirks.overflow_i),
+            ]
+        }
+        quirks(&self.options.quirks)
+            .into_iter()
+            .zip(quirks(&options.quirks))
+            .map(|((quirk, cart), (_, platform))| {
+                (quirk, cart.unwrap_or(false), platform.unwrap_or(false))
+            })
+            .filter(|(_, cart, platform)| cart != platform)
+            .map(|(quirk, cart, platform)| (quirk.to_string(), cart, platform))
+            .collect()
+    }
+
+    /// Returns the name of the platform whose canonical options are closest to this cart's, as
+    /// the number of quirks that differ (see [`Self::quirks_vs_platform`]), plus one if `maxSize`
+    /// differs too. Ties go to the first platform in the order Octo, XO-CHIP, VIP, DREAM 6800,
+    /// ETI-660, CHIP-48 and SUPER-CHIP.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r#"{"program":"","options":{"maxSize":65024,"resClearQuirks":true}}"#).unwrap();
+    /// assert_eq!(cart.closest_platform(), "XO-CHIP");
+    /// ```
+    pub fn closest_platform(&self) -> &'static str {
+        [
+            ("Octo", Platform::Octo),
+            ("XO-CHIP", Platform::XoChip),
+            ("VIP", Platform::Vip),
+            ("DREAM 6800", Platform::Dream6800),
+            ("ETI-660", Platform::Eti660),
+            ("CHIP-48", Platform::Chip48),
+            ("SUPER-CHIP", Platform::Schip),
+        ]
+        .into_iter()
+        .map(|(name, platform)| {
+            let options = Options::new(platform);
+            let differences = self.quirks_vs(&options).len()
+                + usize::from(self.options.max_size != options.max_size);
+            (name, differences)
+        })
+        .min_by_key(|&(_, differences)| differences)
+        .map_or("Octo", |(name, _)| name)
+    }
+
+    /// Returns a summary of the cart for printing when sharing it, with one line each for its
+    /// title and author (if present), closest platform (see [`Self::closest_platform`]),
+    /// tickrate, colors and program length. The colors are drawn as swatches with 24-bit ANSI
+    /// escape codes, in the order `fillColor`, `fillColor2`, `blendColor`, `backgroundColor`,
+    /// `buzzColor` and `quietColor`.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r#"{"program":": main","options":{"tickrate":20},"title":"Demo"}"#).unwrap();
+    /// print!("{}", cart.summary_card());
+    /// ```
+    pub fn summary_card(&self) -> String {
+        let mut card = String::new();
+        if let Some(title) = &self.title {
+            card.push_str(&format!("{}\n", title));
+        }
+        if let Some(author) = &self.author {
+            card.push_str(&format!("Author:   {}\n", author));
+        }
+        card.push_str(&format!("Platform: {}\n", self.closest_platform()));
+        match self.options.tickrate {
+            Some(tickrate) => card.push_str(&format!("Tickrate: {}\n", tickrate)),
+            None => card.push_str("Tickrate: default\n"),
+        }
+        let colors = &self.options.colors;
+        let swatches: Vec<String> = [
+            &colors.fill_color,
+            &colors.fill_color2,
+            &colors.blend_color,
+            &colors.background_color,
+            &colors.buzz_color,
+            &colors.quiet_color,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|color| format!("\x1b[48;2;{};{};{}m  \x1b[0m", color.r, color.g, color.b))
+        .collect();
+        if !swatches.is_empty() {
+            card.push_str(&format!("Colors:   {}\n", swatches.join(" ")));
+        }
+        card.push_str(&format!("Program:  {}\n", self.program_stats()));
+        card
+    }
+
+    /// Returns the number of lines in the program source code. A trailing newline doesn't start a
+    /// new line, so an empty program has no lines.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r#"{"program":": main\n  loop again\n","options":{}}"#).unwrap();
+    /// assert_eq!(cart.program_line_count(), 2);
+    /// ```
+    pub fn program_line_count(&self) -> usize {
+        self.program.lines().count()
+    }
+
+    /// Returns the number of characters in the program source code, counting Unicode scalar values
+    /// rather than bytes.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r##"{"program":"# Ærlig","options":{}}"##).unwrap();
+    /// assert_eq!(cart.program_char_count(), 7);
+    /// ```
+    pub fn program_char_count(&self) -> usize {
+        self.program.chars().count()
+    }
+
+    /// Returns the number of lines and characters in the program source code, like "2 lines, 19
+    /// characters", as shown by [`summary_card`](Self::summary_card).
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r#"{"program":": main","options":{}}"#).unwrap();
+    /// assert_eq!(cart.program_stats(), "1 line, 6 characters");
+    /// ```
+    pub fn program_stats(&self) -> String {
+        format!(
+            "{}, {}",
+            pluralize(self.program_line_count(), "line"),
+            pluralize(self.program_char_count(), "character")
+        )
+    }
+
+    /// Returns a copy of this cart with the given program, and the same options and metadata.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r#"{"program":": main","options":{"tickrate":20}}"#).unwrap();
+    /// let edited = cart.clone_with_program(": main\n  loop again");
+    /// assert_eq!(edited.options, cart.options);
+    /// ```
+    pub fn clone_with_program(&self, program: impl Into<String>) -> OctoCart {
+        OctoCart {
+            program: program.into(),
+            options: clone_options(&self.options),
+            title: self.title.clone(),
+            author: self.author.clone(),
+        }
+    }
+
+    /// Returns the options in the `.octo.rc` configuration format used by C-Octo and
+    /// [`termin-8`](https://crates.io/crates/termin-8).
+    ///
+    /// This is an INI-style list of `key=value` lines, in the order C-Octo writes them.
+    /// `core.rotation`, `core.font` and `core.touch_mode` are always present, since they have
+    /// defaults in the cart's options; every other line is only there if the cart specifies that
+    /// option:
+    ///
+    /// * `core.tickrate`, `core.max_rom`, `core.rotation`, `core.font`, `core.touch_mode` and
+    ///   `core.start_address` are `tickrate`, `maxSize`, `screenRotation`, `fontStyle`,
+    ///   `touchInputMode` and `startAddress`
+    /// * `color.plane0` to `color.plane3` are `backgroundColor`, `fillColor`, `fillColor2` and
+    ///   `blendColor`, as hex codes without the leading `#`
+    /// * `color.background` and `color.sound` are `quietColor` and `buzzColor`; note that the
+    ///   former is the background color while the buzzer is quiet, and not `backgroundColor`
+    /// * `quirks.shift`, `quirks.loadstore`, `quirks.jump0`, `quirks.logic`, `quirks.clip`,
+    ///   `quirks.vblank` and `quirks.vforder` are `shiftQuirks`, `loadStoreQuirks`,
+    ///   `jumpQuirks`, `logicQuirks`, `clipQuirks`, `vBlankQuirks` and `vfOrderQuirks`, as `0` or
+    ///   `1`, and any other quirks are under similar `quirks.` keys
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r#"{"program":"","options":{"tickrate":20,"fontStyle":"eti660"}}"#).unwrap();
+    /// assert_eq!(
+    ///     cart.to_termin8_config(),
+    ///     "core.tickrate=20\ncore.rotation=0\ncore.font=eti_660\ncore.touch_mode=none\n"
+    /// );
+    /// ```
+    pub fn to_termin8_config(&self) -> String {
+        let options = &self.options;
+        let colors = &options.colors;
+        let quirks = &options.quirks;
+        let hex = |color: &Option<Color>| {
+            color
+                .as_ref()
+                .map(|color| format!("{:02X}{:02X}{:02X}", color.r, color.g, color.b))
+        };
+        let flag = |quirk: Option<bool>| quirk.map(|quirk| u8::from(quirk).to_string());
+        let lines = [
+            ("core.tickrate", options.tickrate.map(|n| n.to_string())),
+            ("core.max_rom", options.max_size.map(|n| n.to_string())),
+            (
+                "core.rotation",
+                Some(config_value(&options.screen_rotation)),
+            ),
+            ("core.font", Some(config_font_name(&options.font_style))),
+            (
+                "core.touch_mode",
+                Some(config_value(&options.touch_input_mode)),
+            ),
+            (
+                "core.start_address",
+                options.start_address.map(|n| n.to_string()),
+            ),
+            ("color.plane0", hex(&colors.background_color)),
+            ("color.plane1", hex(&colors.fill_color)),
+            ("color.plane2", hex(&colors.fill_color2)),
+            ("color.plane3", hex(&colors.blend_color)),
+            ("color.background", hex(&colors.quiet_color)),
+            ("color.sound", hex(&colors.buzz_color)),
+            ("quirks.shift", flag(quirks.shift)),
+            ("quirks.loadstore", flag(quirks.load_store)),
+            ("quirks.jump0", flag(quirks.jump0)),
+            ("quirks.logic", flag(quirks.logic)),
+            ("quirks.clip", flag(quirks.clip)),
+            ("quirks.vblank", flag(quirks.vblank)),
+            ("quirks.vforder", flag(quirks.vf_order)),
+            (
+                "quirks.lores_dxy0",
+                quirks.lores_dxy0.as_ref().map(config_value),
+            ),
+            ("quirks.resclear", flag(quirks.res_clear)),
+            ("quirks.delaywrap", flag(quirks.delay_wrap)),
+            ("quirks.hirescollision", flag(quirks.hires_collision)),
+            ("quirks.clipcollision", flag(quirks.clip_collision)),
+            ("quirks.scroll", flag(quirks.scroll)),
+            ("quirks.overflow_i", flag(quirks.overflow_i)),
+        ];
+        lines
+            .iter()
+            .filter_map(|(key, value)| value.as_ref().map(|value| format!("{}={}\n", key, value)))
+            .collect()
+    }
+
+    /// Replaces this cart's options with a copy of the other cart's options, keeping this cart's
+    /// program and metadata.
+    pub fn apply_options_from(&mut self, other: &OctoCart) {
+        self.options = clone_options(&other.options);
+    }
+
+    /// Overrides the options that are present in `patch_json`, a JSON object with the same keys as
+    /// the options in the payload, leaving all other options intact. An option whose value is
+    /// `null` in the patch is cleared, as if the cart didn't specify it.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let mut cart = decart::OctoCart::from_str(r#"{"program":"","options":{"tickrate":7,"maxSize":3215}}"#).unwrap();
+    /// cart.merge_from_json(r#"{"tickrate":20}"#).unwrap();
+    /// assert_eq!(cart.options.tickrate, Some(20));
+    /// assert_eq!(cart.options.max_size, Some(3215));
+    /// ```
+    /// # Errors
+    ///
+    /// Returns `Err` if the patch isn't a JSON object, or if any of its values are invalid for
+    /// their options; see [`Error::ValidationError`]. The options are left unchanged in that case.
+    pub fn merge_from_json(&mut self, patch_json: &str) -> Result<(), Error> {
+        let patch: serde_json::Map<String, serde_json::Value> = serde_json::from_str(patch_json)?;
+        let mut options = self.options_json_value();
+        if let serde_json::Value::Object(options) = &mut options {
+            for (key, value) in patch {
+                if value.is_null() {
+                    options.remove(&key);
+                } else {
+                    options.insert(key, value);
+                }
+            }
+        }
+        // Some options borrow strings while deserializing, which a `Value` can't lend them
+        self.options = serde_json::from_str(&options.to_string())
+            .map_err(|error| invalid_enum_option(&options).unwrap_or(Error::ParsingError(error)))?;
+        Ok(())
+    }
+
+    /// Lists the options that differ from Octo's defaults (see [`Options::default`]), as pairs
+    /// of option keys and values as they would appear in the payload, sorted by key.
+    ///
+    /// Options that the cart doesn't specify are not considered to differ from the defaults.
+    pub fn non_default_options(&self) -> Vec<(String, String)> {
+        let defaults = serde_json::to_value(Options::default())
+            .expect("Options can always be serialized as JSON");
+        match self.options_json_value() {
+            serde_json::Value::Object(options) => options
+                .into_iter()
+                .filter(|(key, value)| defaults.get(key) != Some(value))
+                .map(|(key, value)| match value {
+                    serde_json::Value::String(string) => (key, string),
+                    value => (key, value.to_string()),
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Encodes this cart with the default [`EncodeOptions`], decodes it back, and checks that the
+    /// result is identical, as a self-test before distributing the cart.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if encoding or decoding fails, or a [`Error::RoundtripError`] describing how
+    /// the decoded cart diverges from this one.
+    pub fn validate_roundtrip(&self) -> Result<(), Error> {
+        let mut gif = Vec::new();
+        encode_octocart(&mut gif, self, &EncodeOptions::default())?;
+        let decoded = Self::from_str(&decode_octocart(&gif[..])?)?;
+        if decoded == *self {
+            return Ok(());
+        }
+
+        let mut divergences = Vec::new();
+        if decoded.program != self.program {
+            divergences.push("program".to_string());
+        }
+        if decoded.title != self.title {
+            divergences.push("title".to_string());
+        }
+        if decoded.author != self.author {
+            divergences.push("author".to_string());
+        }
+        let (options, decoded_options) = (self.options_json_value(), decoded.options_json_value());
+        if let (Some(options), Some(decoded_options)) =
+            (options.as_object(), decoded_options.as_object())
+        {
+            let mut keys: Vec<&String> = options.keys().chain(decoded_options.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            divergences.extend(
+                keys.into_iter()
+                    .filter(|&key| options.get(key) != decoded_options.get(key))
+                    .map(|key| format!("option {}", key)),
+            );
+        }
+        Err(Error::RoundtripError(format!(
+            "{} differ(s) after decoding",
+            divergences.join(", ")
+        )))
+    }
+
+    /// Runs every available check on the cart, and returns all the problems found in a single
+    /// report, for instance before submitting the cart to the CHIP-8 Archive.
+    ///
+    /// The errors are an empty program, a `tickrate` of 0, a `startAddress` that leaves no room
+    /// for `maxSize` bytes of program in CHIP-8's 64 KiB address space, and any
+    /// [`validate_roundtrip`](Self::validate_roundtrip) failure. The warnings are those from
+    /// [`validate`](Self::validate), plus quirk combinations that have no effect, like
+    /// `clipCollisionQuirks` without `clipQuirks`.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r#"{"program":"","options":{"tickrate":0}}"#).unwrap();
+    /// assert_eq!(cart.validate_all().errors.len(), 2);
+    /// ```
+    pub fn validate_all(&self) -> DecodeReport {
+        let mut report = DecodeReport {
+            errors: Vec::new(),
+            warnings: self.validate(),
+        };
+        if self.program.trim().is_empty() {
+            report.errors.push("program is empty".to_string());
+        }
+        if self.options.tickrate == Some(0) {
+            report
+                .errors
+                .push("tickrate is 0, so the program never runs".to_string());
+        }
+        if let Some(max_size) = self.options.max_size {
+            if u32::from(self.start_address()) + u32::from(max_size) > 0x10000 {
+                report.errors.push(format!(
+                    "startAddress {:#X} leaves no room for maxSize {} bytes in 64 KiB of memory",
+                    self.start_address(),
+                    max_size
+                ));
+            }
+        }
+        let quirks = &self.options.quirks;
+        if quirks.clip_collision == Some(true) && quirks.clip != Some(true) {
+            report.warnings.push(
+                "clipCollisionQuirks has no effect, since clipQuirks is disabled".to_string(),
+            );
+        }
+        if let Err(error) = self.validate_roundtrip() {
+            report.errors.push(error.to_string());
+        }
+        report
+    }
+
+    /// Returns the line number (starting at 1) and name of every label, constant, alias, macro and
+    /// calculated constant defined in the program, in order.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r#"{"program":":alias x v0\n: main\n  x += 1","options":{}}"#).unwrap();
+    /// assert_eq!(cart.definitions(), vec![(1, "x".to_string()), (2, "main".to_string())]);
+    /// ```
+    pub fn definitions(&self) -> Vec<(usize, String)> {
+        program::definitions(&self.program)
+    }
+
+    /// Returns the text of every string literal in the program, in order, with escape sequences
+    /// like `\n` and `\"` resolved. String literals in comments are ignored.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r#"{"program":":stringmode hello \"HELLO\" { }","options":{}}"#).unwrap();
+    /// assert_eq!(cart.string_literals(), vec!["HELLO".to_string()]);
+    /// ```
+    pub fn string_literals(&self) -> Vec<String> {
+        program::string_literals(&self.program)
+    }
+
+    /// Shrinks the program by removing indentation, trailing whitespace and consecutive blank
+    /// lines, and by collapsing the whitespace between tokens into a single space. String literals
+    /// and comments are left untouched, and line breaks are kept, so that comments still end where
+    /// they did and the program assembles the same as before.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let mut cart = decart::OctoCart::from_str(r#"{"program":": main\n\n\n  v0   :=  1  \n","options":{}}"#).unwrap();
+    /// cart.minify_program();
+    /// assert_eq!(cart.program, ": main\n\nv0 := 1");
+    /// ```
+    pub fn minify_program(&mut self) {
+        self.program = program::minify(&self.program);
+    }
+
+    /// Returns a unified diff from this cart's program to the other cart's program, with three
+    /// lines of context around each change, or an empty string if the programs are identical.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let old = decart::OctoCart::from_str(r#"{"program":": main\n  v0 := 1","options":{}}"#).unwrap();
+    /// let new = decart::OctoCart::from_str(r#"{"program":": main\n  v0 := 2","options":{}}"#).unwrap();
+    /// print!("{}", old.diff_program(&new));
+    /// ```
+    pub fn diff_program(&self, other: &OctoCart) -> String {
+        program::unified_diff(&self.program, &other.program)
+    }
+}
+
+impl OctoCart {
+    /// Leniently deserializes an [`OctoCart`] from a JSON payload, attempting to recover from
+    /// common defects instead of failing outright.
+    ///
+    /// Currently, this recovers payloads that were cut short (because the Octocart's length header
+    /// is too short) by appending the closing quotes and braces needed to make them parseable.
+    /// Any incomplete trailing option is dropped. Stray control characters between JSON tokens,
+    /// which some encoders inject as formatting, are removed first; those inside strings are kept.
+    ///
+    /// Returns the cart along with warnings describing each recovery that was made; if the payload
+    /// was valid to begin with, there are no warnings.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the payload couldn't be recovered.
+    pub fn from_str_lenient(s: &str) -> Result<(Self, Vec<String>), Error> {
+        let (s, removed) = lenient::strip_control_characters(s);
+        let mut warnings = Vec::new();
+        if removed > 0 {
+            warnings.push(format!(
+                "removed {} control character(s) between JSON tokens",
+                removed
+            ));
+        }
+        match serde_json::from_str(&s) {
+            Ok(cart) => Ok((cart, warnings)),
+            Err(err) if err.is_eof() => match lenient::recover_truncated(&s) {
+                Some((cart, warning)) => {
+                    warnings.push(warning);
+                    Ok((cart, warnings))
+                }
+                None => Err(err.into()),
+            },
+            Err(_) => parse_payload(&s).map(|cart| (cart, warnings)),
+        }
+    }
+}
+
+/// Accessors for the color options as [`rgb::RGB8`], for interoperability with graphics crates
+/// that use the `rgb` crate. Each returns `None` if the cart doesn't specify that color.
+#[cfg(feature = "rgb")]
+impl OctoCart {
+    /// The `fillColor` option, used for pixels in the first plane.
+    ///
+    /// Example
+    /// ```
+    /// # use std::str::FromStr;
+    /// let cart = decart::OctoCart::from_str(r##"{"program":"","options":{"fillColor":"#FFCC00"}}"##).unwrap();
+    /// assert_eq!(cart.fill_color_rgb8(), Some(rgb::RGB8::new(0xFF, 0xCC, 0x00)));
+    /// ```
+    pub fn fill_color_rgb8(&self) -> Option<rgb::RGB8> {
+        to_rgb8(&self.options.colors.fill_color)
+    }
+
+    /// The `fillColor2` option, used for pixels in the second plane.
+    pub fn fill_color2_rgb8(&self) -> Option<rgb::RGB8> {
+        to_rgb8(&self.options.colors.fill_color2)
+    }
+
+    /// The `blendColor` option, used for pixels in both planes.
+    pub fn blend_color_rgb8(&self) -> Option<rgb::RGB8> {
+        to_rgb8(&self.options.colors.blend_color)
+    }
+
+    /// The `backgroundColor` option.
+    pub fn background_color_rgb8(&self) -> Option<rgb::RGB8> {
+        to_rgb8(&self.options.colors.background_color)
+    }
+
+    /// The `buzzColor` option, used for the border while the sound timer is active.
+    pub fn buzz_color_rgb8(&self) -> Option<rgb::RGB8> {
+        to_rgb8(&self.options.colors.buzz_color)
+    }
+
+    /// The `quietColor` option, used for the border while the sound timer is inactive.
+    pub fn quiet_color_rgb8(&self) -> Option<rgb::RGB8> {
+        to_rgb8(&self.options.colors.quiet_color)
+    }
+}
+
+/// Formats an option's JSON value as a value in C-Octo's configuration file, without quotes.
+fn config_value<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value).expect("Options can always be serialized as JSON") {
+        serde_json::Value::String(string) => string,
+        value => value.to_string(),
+    }
+}
+
+/// Returns the name C-Octo uses for a font in its configuration file, which differs from the
+/// `fontStyle` option for fonts with a number in their name.
+fn config_font_name(font: &Font) -> String {
+    match font {
+        Font::Octo => "octo".to_string(),
+        Font::Vip => "vip".to_string(),
+        Font::Dream6800 => "dream_6800".to_string(),
+        Font::Eti660 => "eti_660".to_string(),
+        Font::Schip => "schip".to_string(),
+        Font::Fish => "fish".to_string(),
+        Font::AKouZ1 => "akouz1".to_string(),
+        // Fonts from newer versions of octopt, which C-Octo doesn't know either
+        font => config_value(font),
+    }
+}
+
+/// Formats a count followed by a noun, which gets an "s" unless the count is 1.
+fn pluralize(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+#[cfg(feature = "rgb")]
+fn to_rgb8(color: &Option<Color>) -> Option<rgb::RGB8> {
+    color
+        .as_ref()
+        .map(|color| rgb::RGB8::new(color.r, color.g, color.b))
 }
 
 impl FromStr for OctoCart {
@@ -80,85 +924,85 @@ impl fmt::Display for OctoCart {
     }
 }
 
-/// Read and decode Octocart from a file path
-///
-/// # Errors
-///
-/// Returns `Err` if opening the file or decoding the Octocart fails.
-pub fn from_file<P: AsRef<Path>>(path: P) -> Result<OctoCart, Error> {
-    let file = File::open(path)?;
-    let string = decode_octocart(file)?;
-    Ok(OctoCart::from_str(&string)?)
-}
+/// The keys of the color options in the JSON payload.
+const COLOR_KEYS: [&str; 6] = [
+    "fillColor",
+    "fillColor2",
+    "blendColor",
+    "backgroundColor",
+    "buzzColor",
+    "quietColor",
+];
 
-/// Decodes an Octocart, and returns the decoded JSON payload as a string.
+/// Normalizes the color options in a JSON payload to the canonical uppercase `#RRGGBB` form,
+/// expanding `#RGB` shorthand, so that re-encoded carts have consistent color strings.
+///
+/// Colors are always normalized when an [`OctoCart`] is serialized, so this is only needed when
+/// working with the raw payload, like the one returned by [`decode_octocart`]. Color values that
+/// can't be parsed are left untouched.
 ///
 /// Example
-/// ```no_run
-/// let file = std::fs::File::open("test_octocart.gif").unwrap();
-/// let payload: String = decart::decode_octocart(file).unwrap();
-/// ```
-/// You can deserialize this string as an [`OctoCart`]:
-/// ```no_run
-/// # let payload = "{\"tickrate\":7,\"maxSize\":3215,\"screenRotation\":0,\"fontStyle\":\"octo\",\"touchInputMode\":\"none\",\"fillColor\"#FFCC00\",\"fillColor2\":\"#FF6600\",\"blendColor\":\"#662200\",\"backgroundColor\"\"#996600\",\"buzzColor\":\"#FFAA00\",\"quietColor\":\"#000000\",\"shiftQuirks\":0,\"loadStoreQuirks\":0,\"jumpQuirks\":0,\"logicQuirks\":true,\"clipQuirks\":true,\"vBlankQuirks\":true}";
-/// # use std::str::FromStr;
-/// use decart::OctoCart;
-/// let cart: OctoCart = OctoCart::from_str(payload).unwrap();
 /// ```
-/// Note that you can also deserialize from a file directly with [`from_file`]:
-/// ```no_run
-/// use decart::*;
-/// let cart: OctoCart = from_file("test_octocart.gif").unwrap();
+/// let payload = r##"{"program":"","options":{"fillColor":"#fc0"}}"##;
+/// let normalized = decart::normalize_colors(payload).unwrap();
+/// assert_eq!(normalized, r##"{"options":{"fillColor":"#FFCC00"},"program":""}"##);
 /// ```
 /// # Errors
 ///
-/// Returns `Err` if there is a GIF decoding error.
-pub fn decode_octocart<R: Read>(input: R) -> Result<String, Error> {
-    let mut decoder = gif::DecodeOptions::new().read_info(input)?;
-    let global_palette = decoder
-        .global_palette()
-        .ok_or(Error::PaletteError)?
-        .to_vec();
-    let mut size: u32 = 0;
-    let mut first_frame = true;
-    let mut json_string = String::new();
-
-    'frame_loop: while let Some(frame) = decoder.read_next_frame()? {
-        let palette = frame.palette.as_ref().unwrap_or(&global_palette);
-        if first_frame {
-            size = ((u32::from(byte(&frame.buffer, palette, 0))) << 24)
-                | ((u32::from(byte(&frame.buffer, palette, 2))) << 16)
-                | ((u32::from(byte(&frame.buffer, palette, 4))) << 8)
-                | u32::from(byte(&frame.buffer, palette, 6));
-            json_string = String::with_capacity(size as usize);
-        }
-        for pixel in (0..frame.buffer.len()).step_by(2) {
-            if size == 0 {
-                break 'frame_loop;
-            }
-            if first_frame && pixel >= 8 {
-                first_frame = false;
+/// Returns `Err` if the payload isn't valid JSON.
+pub fn normalize_colors(payload: &str) -> Result<String, Error> {
+    let mut json: serde_json::Value = serde_json::from_str(payload)?;
+    if let Some(options) = json.get_mut("options").and_then(|o| o.as_object_mut()) {
+        for key in COLOR_KEYS {
+            if let Some(value) = options.get_mut(key) {
+                if let Some(color) = value.as_str().and_then(|c| Color::from_str(c).ok()) {
+                    *value = serde_json::Value::String(color.to_string());
+                }
             }
-            json_string.push(byte(&frame.buffer, palette, pixel) as char);
-            size -= 1;
         }
     }
-    Ok(json_string)
+    Ok(json.to_string())
 }
 
-fn nybble((r, g, b): (u8, u8, u8)) -> u8 {
-    ((r << 3) & 8) | ((g << 1) & 6) | b & 1
+/// Read and decode Octocart from a file path
+///
+/// # Errors
+///
+/// Returns `Err` if opening the file or decoding the Octocart fails.
+pub fn from_file<P: AsRef<Path>>(path: P) -> Result<OctoCart, Error> {
+    from_file_with_options(path, &DecodeOptions::default())
 }
 
-fn byte(buffer: &[u8], palette: &[u8], i: usize) -> u8 {
-    (nybble(pixel_to_color(buffer[i], palette)) << 4)
-        | nybble(pixel_to_color(buffer[i + 1], palette))
+/// Read and decode Octocart from a file path, with the given [`DecodeOptions`].
+///
+/// # Errors
+///
+/// Returns `Err` if opening the file or decoding the Octocart fails, or if the payload is larger
+/// than allowed by the options.
+pub fn from_file_with_options<P: AsRef<Path>>(
+    path: P,
+    options: &DecodeOptions,
+) -> Result<OctoCart, Error> {
+    let file = File::open(path)?;
+    let string = decode_octocart_with_options(file, options)?;
+    parse_payload(&string)
 }
 
-fn pixel_to_color(pixel: u8, palette: &[u8]) -> (u8, u8, u8) {
-    (
-        palette[(pixel * 3) as usize],
-        palette[(pixel * 3) as usize + 1],
-        palette[(pixel * 3) as usize + 2],
-    )
+/// Read and decode Octocart from a file path, leniently recovering from defects in the payload.
+///
+/// See [`OctoCart::from_str_lenient`] for which defects can be recovered from. In addition, any
+/// invalid UTF-8 in the payload is replaced with U+FFFD REPLACEMENT CHARACTER.
+///
+/// # Errors
+///
+/// Returns `Err` if opening the file or decoding the Octocart fails, or if the payload couldn't be
+/// recovered.
+pub fn from_file_lenient<P: AsRef<Path>>(path: P) -> Result<(OctoCart, Vec<String>), Error> {
+    let file = File::open(path)?;
+    let options = DecodeOptions {
+        lossy_utf8: true,
+        ..DecodeOptions::default()
+    };
+    let string = decode_octocart_with_options(file, &options)?;
+    OctoCart::from_str_lenient(&string)
 }
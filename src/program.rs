@@ -0,0 +1,306 @@
+//! Lightweight analysis of Octo program source code.
+
+/// A token in an Octo program.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Token<'a> {
+    /// Any whitespace-separated word, like an instruction, a label or a directive
+    Word(&'a str),
+    /// The unescaped text of a string literal
+    Str(String),
+}
+
+/// Splits an Octo program into tokens along with their 1-based line numbers, skipping comments.
+pub(crate) fn tokenize(program: &str) -> Vec<(usize, Token<'_>)> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = program.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            _ if c.is_whitespace() => (),
+            '#' => while chars.next_if(|&(_, c)| c != '\n').is_some() {},
+            '"' => {
+                let token_line = line;
+                let mut text = String::new();
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some((_, 'n')) => text.push('\n'),
+                            Some((_, 'r')) => text.push('\r'),
+                            Some((_, 't')) => text.push('\t'),
+                            Some((_, 'v')) => text.push('\x0B'),
+                            Some((_, '0')) => text.push('\0'),
+                            Some((_, c)) => text.push(c),
+                            None => (),
+                        },
+                        '\n' => {
+                            line += 1;
+                            text.push(c);
+                        }
+                        _ => text.push(c),
+                    }
+                }
+                tokens.push((token_line, Token::Str(text)));
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|&(_, c)| !c.is_whitespace()) {
+                    end = i + c.len_utf8();
+                }
+                tokens.push((line, Token::Word(&program[start..end])));
+            }
+        }
+    }
+    tokens
+}
+
+/// The directives that define a name given as the following token.
+const DEFINING_DIRECTIVES: [&str; 5] = [":", ":const", ":alias", ":macro", ":calc"];
+
+/// Returns the line number and name of every definition in an Octo program.
+pub(crate) fn definitions(program: &str) -> Vec<(usize, String)> {
+    let tokens = tokenize(program);
+    tokens
+        .windows(2)
+        .filter_map(|pair| match pair {
+            // A directive without a name isn't a definition of the next directive
+            [(line, Token::Word(directive)), (_, Token::Word(name))]
+                if DEFINING_DIRECTIVES.contains(directive)
+                    && !DEFINING_DIRECTIVES.contains(name) =>
+            {
+                Some((*line, name.to_string()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns whether an Octo program selects drawing planes with the XO-CHIP `plane` instruction.
+pub(crate) fn uses_planes(program: &str) -> bool {
+    tokenize(program)
+        .iter()
+        .any(|(_, token)| *token == Token::Word("plane"))
+}
+
+/// Returns the unescaped text of every string literal in an Octo program.
+pub(crate) fn string_literals(program: &str) -> Vec<String> {
+    tokenize(program)
+        .into_iter()
+        .filter_map(|(_, token)| match token {
+            Token::Str(text) => Some(text),
+            Token::Word(_) => None,
+        })
+        .collect()
+}
+
+/// Removes redundant whitespace from an Octo program, outside of string literals and comments.
+/// Line breaks are kept, but runs of blank lines are collapsed into one.
+pub(crate) fn minify(program: &str) -> String {
+    let mut minified = String::with_capacity(program.len());
+    let mut space = false;
+    let mut newlines = 0;
+    let mut chars = program.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                space = false;
+                newlines += 1;
+            }
+            _ if c.is_whitespace() => space = true,
+            _ => {
+                if !minified.is_empty() {
+                    if newlines > 0 {
+                        minified.push_str(if newlines > 1 { "\n\n" } else { "\n" });
+                    } else if space {
+                        minified.push(' ');
+                    }
+                }
+                let token_start = minified.is_empty() || newlines > 0 || space;
+                space = false;
+                newlines = 0;
+                minified.push(c);
+                match c {
+                    // Comments run until the end of the line
+                    '#' if token_start => {
+                        let comment = chars.as_str().split('\n').next().unwrap_or("");
+                        minified.push_str(comment.trim_end());
+                        chars = chars.as_str()[comment.len()..].chars();
+                    }
+                    '"' if token_start => {
+                        let mut escaped = false;
+                        for c in chars.by_ref() {
+                            minified.push(c);
+                            match c {
+                                _ if escaped => escaped = false,
+                                '\\' => escaped = true,
+                                '"' => break,
+                                _ => (),
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+    minified
+}
+
+/// A line in a diff between two programs.
+enum Line<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Finds a shortest sequence of line deletions and insertions that turns `old` into `new`, using
+/// Myers' diff algorithm.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m + 1;
+    // `v[k]` is the furthest x reached on diagonal k = x - y, for each number of edits d
+    let mut v = vec![0; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+    'search: for d in 0..=n + m {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Backtrack through the trace to recover the edits
+    let mut lines = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let (d, k) = (d as isize, x - y);
+        let i = (k + offset) as usize;
+        let previous_k = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = v[(previous_k + offset) as usize];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            lines.push(Line::Equal(old[x as usize]));
+        }
+        if d > 0 {
+            if x == previous_x {
+                lines.push(Line::Insert(new[previous_y as usize]));
+            } else {
+                lines.push(Line::Delete(old[previous_x as usize]));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    lines.reverse();
+    lines
+}
+
+/// The number of unchanged lines shown around each change in a unified diff.
+const CONTEXT: usize = 3;
+
+/// Returns a unified diff between two programs, or an empty string if they're identical.
+pub(crate) fn unified_diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&old, &new);
+    let changes: Vec<usize> = (0..lines.len())
+        .filter(|&i| !matches!(lines[i], Line::Equal(_)))
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let mut diff = String::from("--- a/program.8o\n+++ b/program.8o\n");
+    // The line numbers in the old and new programs at the start of the current hunk
+    let (mut old_line, mut new_line) = (0, 0);
+    let mut position = 0;
+    let mut changes = changes.iter().peekable();
+    while let Some(&first) = changes.next() {
+        // Changes that are close enough to share context lines go in the same hunk
+        let mut last = first;
+        while let Some(&&next) = changes.peek() {
+            if next - last > 2 * CONTEXT {
+                break;
+            }
+            last = next;
+            changes.next();
+        }
+        let start = first.saturating_sub(CONTEXT).max(position);
+        let end = (last + 1 + CONTEXT).min(lines.len());
+        for line in &lines[position..start] {
+            old_line += usize::from(!matches!(line, Line::Insert(_)));
+            new_line += usize::from(!matches!(line, Line::Delete(_)));
+        }
+        let hunk = &lines[start..end];
+        let old_length = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Insert(_)))
+            .count();
+        let new_length = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Delete(_)))
+            .count();
+        // Empty ranges are numbered by the line before them
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_line + usize::from(old_length > 0),
+            old_length,
+            new_line + usize::from(new_length > 0),
+            new_length
+        ));
+        for line in hunk {
+            let (prefix, text) = match line {
+                Line::Equal(text) => (' ', text),
+                Line::Delete(text) => ('-', text),
+                Line::Insert(text) => ('+', text),
+            };
+            diff.push(prefix);
+            diff.push_str(text);
+            diff.push('\n');
+        }
+        old_line += old_length;
+        new_line += new_length;
+        position = end;
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minify_keeps_tokens() {
+        let program = ": main\t\n\n\n    text  :=  \"  two   spaces \\\" # not a comment \"  # a   comment\n  :stringmode  s  \"\tA\"  {  }\n";
+        let tokens = |program| -> Vec<Token<'_>> {
+            tokenize(program)
+                .into_iter()
+                .map(|(_, token)| token)
+                .collect()
+        };
+        let minified = minify(program);
+        assert_ne!(minified, program);
+        assert_eq!(tokens(&minified), tokens(program));
+        assert!(minified.contains("# a   comment"));
+    }
+}
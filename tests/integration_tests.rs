@@ -1,7 +1,69 @@
 #[cfg(test)]
 use assert_json_diff::assert_json_eq;
-use decart::{from_file, OctoCart};
+use decart::{
+    cart_info, decode_frame, decode_octocart, decode_octocart_with_gif_options,
+    decode_octocart_with_options, decode_options_only, decode_prefix, decode_with_layout,
+    decode_with_palette, encode_octocart, from_file, from_file_with_options, normalize_colors,
+    thumbnail_ascii, BitLayout, DecodeOptions, Decoder, EncodeOptions, Error, OctoCart,
+    MIN_PALETTE_SIZE,
+};
 use serde_json::{json, Value};
+use std::io::Read;
+use std::str::FromStr;
+
+/// A 16-color palette where the least significant bits of color `i` encode the nybble `i`.
+fn nybble_palette() -> Vec<u8> {
+    (0..16u8)
+        .flat_map(|i| [(i >> 3) & 1, (i >> 1) & 3, i & 1])
+        .collect()
+}
+
+/// Builds a GIF with the given payload (prefixed with its 32-bit length) spread over frames of
+/// the given dimensions.
+fn build_cart(payload: &[u8], width: u16, height: u16) -> Vec<u8> {
+    build_cart_with_length(payload, payload.len() as u32, width, height)
+}
+
+/// Like `build_cart`, but with an arbitrary length in the header.
+fn build_cart_with_length(payload: &[u8], length: u32, width: u16, height: u16) -> Vec<u8> {
+    let pixels = payload_pixels(payload, length);
+    let frame_size = usize::from(width) * usize::from(height);
+    let frames: Vec<(u16, u16, &[u8])> = pixels
+        .chunks(frame_size)
+        .map(|chunk| (width, height, chunk))
+        .collect();
+    build_gif(&nybble_palette(), &frames)
+}
+
+/// Returns the pixels that carry the payload, prefixed with the given 32-bit length, as indices
+/// into `nybble_palette`.
+fn payload_pixels(payload: &[u8], length: u32) -> Vec<u8> {
+    let mut bytes = length.to_be_bytes().to_vec();
+    bytes.extend_from_slice(payload);
+    bytes.iter().flat_map(|b| [b >> 4, b & 0xF]).collect()
+}
+
+/// Builds a GIF with the given global palette and frames, as `(width, height, pixels)`. The pixels
+/// of each frame are padded with index 0 to fill it.
+fn build_gif(palette: &[u8], frames: &[(u16, u16, &[u8])]) -> Vec<u8> {
+    let width = frames.iter().map(|&(width, _, _)| width).max().unwrap_or(0);
+    let height = frames
+        .iter()
+        .map(|&(_, height, _)| height)
+        .max()
+        .unwrap_or(0);
+    let mut gif = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut gif, width, height, palette).unwrap();
+        for &(width, height, pixels) in frames {
+            let mut buffer = pixels.to_vec();
+            buffer.resize(usize::from(width) * usize::from(height), 0);
+            let frame = gif::Frame::from_indexed_pixels(width, height, &buffer, None);
+            encoder.write_frame(&frame).unwrap();
+        }
+    }
+    gif
+}
 
 #[test]
 fn minimal_from_file() {
@@ -13,3 +75,1154 @@ fn minimal_from_file() {
         json!({"program":": main","options":{"tickrate":7,"fillColor":"#FFCC00","fillColor2":"#FF6600","blendColor":"#662200","backgroundColor":"#996600","buzzColor":"#FFAA00","quietColor":"#000000","shiftQuirks":false,"loadStoreQuirks":false,"vfOrderQuirks":false,"clipQuirks":true,"vBlankQuirks":true,"jumpQuirks":false,"screenRotation":0,"maxSize":3215,"touchInputMode":"none","logicQuirks":true,"fontStyle":"octo"}})
     );
 }
+
+#[test]
+fn header_boundary() {
+    // 4 header bytes take up the first 8 pixels; the body must start at the 9th pixel
+    let payload = b"0123456789abcdef";
+    let gif = build_cart(payload, 8, 8);
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), "0123456789abcdef");
+
+    // In a narrower frame, the header spans the first two rows
+    let gif = build_cart(payload, 4, 16);
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), "0123456789abcdef");
+}
+
+#[test]
+fn prefix_from_file() {
+    let file = std::fs::File::open("tests/test_carts/minimal.gif").unwrap();
+    let prefix = decode_prefix(file, 20).unwrap();
+    assert_eq!(prefix.len(), 20);
+
+    let file = std::fs::File::open("tests/test_carts/minimal.gif").unwrap();
+    let payload = decode_octocart(file).unwrap();
+    assert!(payload.starts_with(&prefix));
+
+    let file = std::fs::File::open("tests/test_carts/minimal.gif").unwrap();
+    assert_eq!(decode_prefix(file, usize::MAX).unwrap(), payload);
+
+    // A character cut off by the prefix is left out, rather than replaced
+    let payload = "{\"program\":\"# café\"}";
+    let gif = build_cart(payload.as_bytes(), 16, 16);
+    let cut = payload.find('é').unwrap() + 1;
+    assert_eq!(decode_prefix(&gif[..], cut).unwrap(), &payload[..cut - 1]);
+    assert_eq!(
+        decode_prefix(&gif[..], cut + 1).unwrap(),
+        &payload[..cut + 1]
+    );
+
+    // A GIF that ends before the payload does still has a cut character, which isn't left out
+    let gif = build_cart_with_length(&payload.as_bytes()[..cut], 100, 4, 11);
+    assert!(matches!(
+        decode_octocart(&gif[..]),
+        Err(Error::Utf8Error(_))
+    ));
+}
+
+#[test]
+fn gameboy_color_scheme() {
+    let mut cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    cart.set_color_scheme("gameboy").unwrap();
+    let json: Value = serde_json::from_str(&cart.options.to_string()).unwrap();
+    assert_eq!(json["fillColor"], "#0F380F");
+    assert_eq!(json["fillColor2"], "#306230");
+    assert_eq!(json["blendColor"], "#8BAC0F");
+    assert_eq!(json["backgroundColor"], "#9BBC0F");
+    assert_eq!(json["buzzColor"], "#306230");
+    assert_eq!(json["quietColor"], "#9BBC0F");
+
+    // An unknown scheme leaves the colors untouched
+    assert!(cart.set_color_scheme("no such scheme").is_err());
+    let json: Value = serde_json::from_str(&cart.options.to_string()).unwrap();
+    assert_eq!(json["fillColor"], "#0F380F");
+}
+
+#[test]
+fn normalized_colors() {
+    let payload = json!({"program":": main","options":{"tickrate":7,"fillColor":"#ffcc00","fillColor2":"#FF6600","blendColor":"#620","backgroundColor":"#9a6","buzzColor":"FFAA00","quietColor":"not a color"}}).to_string();
+    let json: Value = serde_json::from_str(&normalize_colors(&payload).unwrap()).unwrap();
+    assert_json_eq!(
+        json,
+        json!({"program":": main","options":{"tickrate":7,"fillColor":"#FFCC00","fillColor2":"#FF6600","blendColor":"#662200","backgroundColor":"#99AA66","buzzColor":"#FFAA00","quietColor":"not a color"}})
+    );
+}
+
+#[test]
+fn min_carrier_dimensions() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let (width, height) = cart.min_carrier_dimensions();
+    let capacity = (width * height / 2) as usize;
+    let payload_length = 4 + cart.to_string().len();
+    assert!(capacity >= payload_length);
+    // Snug: dropping a row would no longer fit the payload
+    assert!(width * (height - 1) / 2 < payload_length as u32);
+    assert!(width.abs_diff(height) <= 1);
+}
+
+#[test]
+fn truncated_payload_lenient() {
+    let payload =
+        r#"{"program":": main\nloop again","options":{"tickrate":7,"fontStyle":"schip"}}"#;
+    let cart = OctoCart::from_str(payload).unwrap();
+
+    // The length header is three bytes too short, so `"}}` is missing
+    let gif = build_cart_with_length(payload.as_bytes(), payload.len() as u32 - 3, 16, 16);
+    let truncated = decode_octocart(&gif[..]).unwrap();
+    assert!(OctoCart::from_str(&truncated).is_err());
+    let (recovered, warnings) = OctoCart::from_str_lenient(&truncated).unwrap();
+    assert_eq!(recovered.program, cart.program);
+    assert_eq!(recovered.options, cart.options);
+    assert_eq!(warnings.len(), 1);
+
+    // Cut in the middle of an option value, which has to be dropped
+    let truncated = &payload[..payload.len() - 6];
+    let (recovered, warnings) = OctoCart::from_str_lenient(truncated).unwrap();
+    assert_eq!(recovered.program, cart.program);
+    assert_eq!(recovered.options.tickrate, Some(7));
+    assert_eq!(recovered.options.font_style, octopt::Font::Octo);
+    assert_eq!(warnings.len(), 1);
+
+    // Valid payloads parse without warnings
+    let (_, warnings) = OctoCart::from_str_lenient(payload).unwrap();
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn visible_data_decodes_identically() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+
+    let mut hidden = Vec::new();
+    encode_octocart(&mut hidden, &cart, &EncodeOptions::default()).unwrap();
+    let mut visible = Vec::new();
+    let options = EncodeOptions {
+        visible_data: true,
+        ..EncodeOptions::default()
+    };
+    encode_octocart(&mut visible, &cart, &options).unwrap();
+
+    assert_ne!(hidden, visible);
+    assert_eq!(decode_octocart(&hidden[..]).unwrap(), cart.to_string());
+    assert_eq!(decode_octocart(&visible[..]).unwrap(), cart.to_string());
+
+    // Hidden data never strays further than the least significant bits from the background color
+    let mut decoder = gif::DecodeOptions::new().read_info(&hidden[..]).unwrap();
+    let palette = decoder.global_palette().unwrap().to_vec();
+    let frame = decoder.read_next_frame().unwrap().unwrap();
+    for &pixel in frame.buffer.iter() {
+        let color = &palette[usize::from(pixel) * 3..usize::from(pixel) * 3 + 3];
+        assert_eq!(
+            [color[0] & !1, color[1] & !3, color[2] & !1],
+            [0x98, 0x64, 0x00]
+        );
+    }
+}
+
+#[test]
+fn options_json_value() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let options = cart.options_json_value();
+    assert_eq!(options["tickrate"], 7);
+    assert_eq!(options["fontStyle"], "octo");
+}
+
+#[test]
+fn frames_of_differing_sizes() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let options = EncodeOptions {
+        // 5x7 has an odd number of pixels, so its last pixel is skipped
+        frame_dimensions: vec![(16, 8), (5, 7), (30, 3), (10, 10)],
+        ..EncodeOptions::default()
+    };
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &options).unwrap();
+
+    let mut decoder = gif::DecodeOptions::new().read_info(&gif[..]).unwrap();
+    assert_eq!((decoder.width(), decoder.height()), (30, 10));
+    let mut sizes = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        sizes.push((frame.width, frame.height));
+    }
+    // The payload is over 400 bytes, so the last size is repeated
+    assert_eq!(&sizes[..4], &[(16, 8), (5, 7), (30, 3), (10, 10)]);
+    assert!(sizes[4..].iter().all(|&size| size == (10, 10)));
+
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), cart.to_string());
+
+    // Only the first frame has to fit the 8 pixels of the payload length
+    let options = EncodeOptions {
+        frame_dimensions: vec![(3, 1)],
+        ..EncodeOptions::default()
+    };
+    assert!(matches!(
+        encode_octocart(&mut Vec::new(), &cart, &options),
+        Err(Error::DimensionsError)
+    ));
+    let options = EncodeOptions {
+        frame_dimensions: vec![(4, 2), (3, 1)],
+        ..EncodeOptions::default()
+    };
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &options).unwrap();
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), cart.to_string());
+}
+
+#[test]
+fn max_bytes_limit() {
+    let options = DecodeOptions {
+        max_bytes: 100,
+        ..DecodeOptions::default()
+    };
+    let result = from_file_with_options("tests/test_carts/minimal.gif", &options);
+    assert!(matches!(result, Err(Error::PayloadTooLarge)));
+
+    // A header claiming a huge payload is rejected before anything is allocated
+    let gif = build_cart_with_length(b"{}", u32::MAX, 8, 8);
+    assert!(matches!(
+        decode_octocart(&gif[..]),
+        Err(Error::PayloadTooLarge)
+    ));
+
+    let options = DecodeOptions {
+        max_bytes: 1000,
+        ..DecodeOptions::default()
+    };
+    assert!(from_file_with_options("tests/test_carts/minimal.gif", &options).is_ok());
+}
+
+#[test]
+fn program_definitions() {
+    let program = r#"# A "program" with : pretend definitions in comments
+:alias px v1
+:const SPEED 3
+:macro wait { loop vf := delay while vf != 0 again }
+
+: main
+  px := SPEED # : not-a-label
+  draw
+
+:calc HALF { SPEED / 2 }
+: draw
+  : inner
+  return
+"#;
+    let cart = OctoCart::from_str(&json!({"program": program, "options": {}}).to_string()).unwrap();
+    assert_eq!(
+        cart.definitions(),
+        vec![
+            (2, "px".to_string()),
+            (3, "SPEED".to_string()),
+            (4, "wait".to_string()),
+            (6, "main".to_string()),
+            (10, "HALF".to_string()),
+            (11, "draw".to_string()),
+            (12, "inner".to_string()),
+        ]
+    );
+
+    let cart =
+        OctoCart::from_str(&json!({ "program": ":\n: a", "options": {} }).to_string()).unwrap();
+    assert_eq!(cart.definitions(), vec![(2, "a".to_string())]);
+}
+
+/// A reader that delivers its data a few bytes at a time, interrupted every other read.
+struct ChunkedReader<'a> {
+    data: &'a [u8],
+    reads: usize,
+}
+
+impl Read for ChunkedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reads += 1;
+        if self.reads.is_multiple_of(2) {
+            return Err(std::io::ErrorKind::Interrupted.into());
+        }
+        let n = (self.reads % 7 + 1).min(buf.len()).min(self.data.len());
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn chunked_reader() {
+    let gif = std::fs::read("tests/test_carts/minimal.gif").unwrap();
+    let reader = ChunkedReader {
+        data: &gif,
+        reads: 0,
+    };
+    assert_eq!(
+        decode_octocart(reader).unwrap(),
+        decode_octocart(&gif[..]).unwrap()
+    );
+}
+
+#[test]
+fn reencode_with_original_frame_count() {
+    let info = cart_info(std::fs::File::open("tests/test_carts/minimal.gif").unwrap()).unwrap();
+    assert_eq!((info.width, info.height), (160, 128));
+    assert_eq!(info.frame_count, 1);
+    let payload = decode_octocart(std::fs::File::open("tests/test_carts/minimal.gif").unwrap());
+    assert_eq!(info.payload_length as usize, payload.unwrap().len());
+
+    // With the original frame size and count
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let options = EncodeOptions {
+        frame_dimensions: vec![(info.width, info.height)],
+        target_frames: Some(info.frame_count),
+        ..EncodeOptions::default()
+    };
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &options).unwrap();
+    let reencoded = cart_info(&gif[..]).unwrap();
+    assert_eq!((reencoded.width, reencoded.height), (160, 128));
+    assert_eq!(reencoded.frame_count, 1);
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), cart.to_string());
+
+    // Without frame dimensions, the payload is spread evenly over the frames
+    let options = EncodeOptions {
+        target_frames: Some(3),
+        ..EncodeOptions::default()
+    };
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &options).unwrap();
+    assert_eq!(cart_info(&gif[..]).unwrap().frame_count, 3);
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), cart.to_string());
+
+    // Spreading a short cart over many frames still leaves room for the header
+    let short = OctoCart::from_str(r#"{"program":": main","options":{}}"#).unwrap();
+    let options = EncodeOptions {
+        target_frames: Some(200),
+        ..EncodeOptions::default()
+    };
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &short, &options).unwrap();
+    assert_eq!(cart_info(&gif[..]).unwrap().frame_count, 200);
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), short.to_string());
+
+    // More frames than the payload needs are padded
+    let options = EncodeOptions {
+        frame_dimensions: vec![(info.width, info.height)],
+        target_frames: Some(4),
+        ..EncodeOptions::default()
+    };
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &options).unwrap();
+    assert_eq!(cart_info(&gif[..]).unwrap().frame_count, 4);
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), cart.to_string());
+
+    // Too few frames of a given size for the payload
+    let options = EncodeOptions {
+        frame_dimensions: vec![(8, 8)],
+        target_frames: Some(2),
+        ..EncodeOptions::default()
+    };
+    assert!(matches!(
+        encode_octocart(&mut Vec::new(), &cart, &options),
+        Err(Error::DimensionsError)
+    ));
+}
+
+#[test]
+fn non_default_options() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let overrides: Vec<(String, String)> = [
+        ("backgroundColor", "#996600"),
+        ("blendColor", "#662200"),
+        ("buzzColor", "#FFAA00"),
+        ("clipQuirks", "true"),
+        ("fillColor", "#FFCC00"),
+        ("fillColor2", "#FF6600"),
+        ("logicQuirks", "true"),
+        ("maxSize", "3215"),
+        ("quietColor", "#000000"),
+        ("tickrate", "7"),
+        ("vBlankQuirks", "true"),
+    ]
+    .iter()
+    .map(|(key, value)| (key.to_string(), value.to_string()))
+    .collect();
+    assert_eq!(cart.non_default_options(), overrides);
+}
+
+#[test]
+fn junk_after_trailer() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let mut gif = std::fs::read("tests/test_carts/minimal.gif").unwrap();
+    let payload = decode_octocart(&gif[..]).unwrap();
+    assert_eq!(gif.last(), Some(&0x3B));
+    gif.extend_from_slice(b"\x00\x21\xFFjunk that isn't a GIF block");
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), payload);
+    assert_eq!(cart_info(&gif[..]).unwrap().frame_count, 1);
+
+    // Also when the payload is spread over several frames, all the way up to the trailer
+    let mut gif = Vec::new();
+    let options = EncodeOptions {
+        frame_dimensions: vec![(8, 8)],
+        ..EncodeOptions::default()
+    };
+    encode_octocart(&mut gif, &cart, &options).unwrap();
+    let frame_count = cart_info(&gif[..]).unwrap().frame_count;
+    gif.extend_from_slice(&[0x2C, 0xFF, 0x3B, 0x00]);
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), cart.to_string());
+    assert_eq!(cart_info(&gif[..]).unwrap().frame_count, frame_count);
+}
+
+#[test]
+fn validate_roundtrip() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    cart.validate_roundtrip().unwrap();
+
+    let mut cart = cart;
+    cart.program = ": main\n  loop again # ünïcödé".to_string();
+    cart.validate_roundtrip().unwrap();
+}
+
+#[test]
+fn max_size_as_string() {
+    // octopt already accepts numeric options serialized as strings
+    let cart = OctoCart::from_str(r#"{"program":"","options":{"maxSize":"3215"}}"#).unwrap();
+    assert_eq!(cart.options.max_size, Some(3215));
+    let cart = OctoCart::from_str(r#"{"program":"","options":{"maxSize":3215}}"#).unwrap();
+    assert_eq!(cart.options.max_size, Some(3215));
+    assert_eq!(cart.options_json_value()["maxSize"], 3215);
+}
+
+#[test]
+fn decode_stops_at_declared_length() {
+    // The data after the declared length spills over into two more frames, which must be ignored
+    let data = b"0123456789abcdefghijklmnopqrstuvwxyz0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    let gif = build_cart_with_length(data, 40, 8, 8);
+    assert_eq!(cart_info(&gif[..]).unwrap().frame_count, 3);
+    assert_eq!(decode_octocart(&gif[..]).unwrap().as_bytes(), &data[..40]);
+
+    let mut decoder = Decoder::new(&gif[..], &DecodeOptions::default()).unwrap();
+    assert_eq!(decoder.payload_length(), None);
+    // The first frame holds 28 bytes after the header, and the rest are in the second frame
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap(), &data[..28]);
+    assert_eq!(decoder.payload_length(), Some(40));
+    assert_eq!(decoder.bytes_decoded(), 28);
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap(), &data[28..40]);
+    assert_eq!(decoder.bytes_decoded(), 40);
+    assert_eq!(decoder.read_next_frame().unwrap(), None);
+    assert_eq!(decoder.bytes_decoded(), 40);
+
+    // Padding pixels in the last frame are ignored too
+    let gif = build_cart(b"short", 8, 8);
+    let mut decoder = Decoder::new(&gif[..], &DecodeOptions::default()).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap(), b"short");
+    assert_eq!(decoder.read_next_frame().unwrap(), None);
+    assert_eq!(decoder.bytes_decoded(), 5);
+}
+
+#[test]
+fn minify_program() {
+    let program = "# A comment with a \"quote\"   \n\n\n:alias  x  v0\n\n: main\t\n    x  :=  1   # set \"x\"\n    text := \"  two   spaces # not a comment \"\n\n\n\n\n    loop again  \n";
+    let mut cart =
+        OctoCart::from_str(&json!({ "program": program, "options": {} }).to_string()).unwrap();
+    let definitions = cart.definitions();
+    cart.minify_program();
+    assert_eq!(
+        cart.program,
+        "# A comment with a \"quote\"\n\n:alias x v0\n\n: main\nx := 1 # set \"x\"\ntext := \"  two   spaces # not a comment \"\n\nloop again"
+    );
+    // The same names are still defined, in the same order
+    assert_eq!(
+        cart.definitions()
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>(),
+        definitions
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn decode_with_palette_override() {
+    let payload = b"{\"program\":\": main\",\"options\":{}}";
+    let pixels = payload_pixels(payload, payload.len() as u32);
+
+    // The embedded palette is reversed, so every nybble decodes to its complement
+    let wrong_palette: Vec<u8> = nybble_palette()
+        .chunks(3)
+        .rev()
+        .flatten()
+        .copied()
+        .collect();
+    let gif = build_gif(&wrong_palette, &[(16, 16, &pixels)]);
+    assert!(decode_octocart(&gif[..]).is_err());
+    assert_eq!(
+        decode_with_palette(&gif[..], &nybble_palette())
+            .unwrap()
+            .as_bytes(),
+        payload
+    );
+}
+
+#[test]
+fn decode_with_rgba_palette_override() {
+    let payload = b"{\"program\":\": main\",\"options\":{}}";
+    let gif = build_gif(
+        &[0; 3 * 16],
+        &[(16, 16, &payload_pixels(payload, payload.len() as u32))],
+    );
+
+    // 48 opaque colors, so the palette's length is divisible by 3 as well as by 4
+    let rgba: Vec<u8> = nybble_palette()
+        .chunks(3)
+        .cycle()
+        .take(48)
+        .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xFF])
+        .collect();
+    assert_eq!(
+        decode_with_palette(&gif[..], &rgba).unwrap().as_bytes(),
+        payload
+    );
+}
+
+#[test]
+fn merge_from_json() {
+    let mut cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let before = cart.options_json_value();
+    cart.merge_from_json(r##"{"tickrate":30,"fillColor":"#00FF00"}"##)
+        .unwrap();
+    let mut expected = before.clone();
+    expected["tickrate"] = json!(30);
+    expected["fillColor"] = json!("#00FF00");
+    assert_json_eq!(cart.options_json_value(), expected);
+
+    // An invalid patch leaves the options as they were
+    assert!(cart.merge_from_json(r#"{"tickrate":true}"#).is_err());
+    assert!(cart.merge_from_json("[]").is_err());
+    assert_json_eq!(cart.options_json_value(), expected);
+
+    // Null values clear options of any type
+    cart.merge_from_json(
+        r#"{"tickrate":null,"logicQuirks":null,"fillColor":null,"screenRotation":null}"#,
+    )
+    .unwrap();
+    assert_eq!(cart.options.tickrate, None);
+    assert_eq!(cart.options.quirks.logic, None);
+    assert_eq!(cart.options.colors.fill_color, None);
+    let options = expected.as_object_mut().unwrap();
+    for key in ["tickrate", "logicQuirks", "fillColor"] {
+        options.remove(key);
+    }
+    assert_json_eq!(cart.options_json_value(), expected);
+}
+
+#[test]
+fn thumbnail_dimensions() {
+    // The 160x128 frame is scaled down to 40 columns, and half as many rows as that ratio implies
+    let file = std::fs::File::open("tests/test_carts/minimal.gif").unwrap();
+    let thumbnail = thumbnail_ascii(file, 40).unwrap();
+    let lines: Vec<&str> = thumbnail.lines().collect();
+    assert_eq!(lines.len(), 16);
+    assert!(lines.iter().all(|line| line.len() == 40));
+
+    // Thumbnails can't be wider than the frame
+    let gif = build_cart(b"0123456789", 8, 8);
+    let thumbnail = thumbnail_ascii(&gif[..], 40).unwrap();
+    assert_eq!(thumbnail.lines().count(), 4);
+    assert!(thumbnail.lines().all(|line| line.len() == 8));
+
+    // A single character can cover a large, bright frame
+    let gif = build_gif(&[255, 255, 255], &[(256, 128, &[])]);
+    assert_eq!(thumbnail_ascii(&gif[..], 1).unwrap(), "@\n");
+}
+
+#[test]
+fn empty_frames_are_skipped() {
+    let data = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let pixels = payload_pixels(data, data.len() as u32);
+    let (first, second) = pixels.split_at(64);
+
+    // Empty placeholder frames before, between and after the two data frames
+    let gif = build_gif(
+        &nybble_palette(),
+        &[
+            (0, 0, &[]),
+            (8, 8, first),
+            (0, 0, &[]),
+            (8, 8, second),
+            (0, 0, &[]),
+        ],
+    );
+    assert_eq!(decode_octocart(&gif[..]).unwrap().as_bytes(), data);
+    let info = cart_info(&gif[..]).unwrap();
+    assert_eq!(info.frame_count, 5);
+    assert_eq!(info.payload_length, data.len() as u32);
+}
+
+#[test]
+fn validation_error_lists_allowed_values() {
+    let mut cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let error = cart
+        .merge_from_json(r#"{"fontStyle":"comicsans"}"#)
+        .unwrap_err();
+    assert!(matches!(error, Error::ValidationError(..)));
+    assert_eq!(
+        error.to_string(),
+        "Invalid value comicsans for fontStyle, expected one of: octo, vip, dream6800, eti660, schip, fish, akouz1"
+    );
+
+    let error = cart
+        .merge_from_json(r#"{"screenRotation":45}"#)
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .ends_with("expected one of: 0, 90, 180, 270"));
+
+    let payload = r#"{"program":"","options":{"touchInputMode":"mouse"}}"#;
+    let error = OctoCart::from_str_lenient(payload).unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("Invalid value mouse for touchInputMode"));
+}
+
+#[test]
+fn loop_count() {
+    // The Netscape looping extension ends with the loop count as a little-endian 16-bit number
+    fn loop_extension(gif: &[u8]) -> Option<[u8; 4]> {
+        let start = gif.windows(11).position(|w| w == b"NETSCAPE2.0")? + 11;
+        gif.get(start..start + 4)?.try_into().ok()
+    }
+
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &EncodeOptions::default()).unwrap();
+    assert_eq!(loop_extension(&gif), Some([3, 1, 0, 0]));
+
+    let options = EncodeOptions {
+        loop_count: 300,
+        ..EncodeOptions::default()
+    };
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &options).unwrap();
+    assert_eq!(loop_extension(&gif), Some([3, 1, 0x2C, 0x01]));
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), cart.to_string());
+}
+
+#[cfg(feature = "rgb")]
+#[test]
+fn rgb8_colors() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    assert_eq!(
+        cart.fill_color_rgb8(),
+        Some(rgb::RGB8::new(0xFF, 0xCC, 0x00))
+    );
+    assert_eq!(cart.quiet_color_rgb8(), Some(rgb::RGB8::new(0, 0, 0)));
+    let cart = OctoCart::from_str(r#"{"program":"","options":{}}"#).unwrap();
+    assert_eq!(cart.background_color_rgb8(), None);
+}
+
+#[test]
+fn custom_gif_options() {
+    let gif = std::fs::read("tests/test_carts/minimal.gif").unwrap();
+    let payload = decode_octocart(&gif[..]).unwrap();
+
+    // The 160x128 frame needs more memory than this
+    let mut options = gif::DecodeOptions::new();
+    options.set_memory_limit(gif::MemoryLimit(1024));
+    assert!(matches!(
+        decode_octocart_with_gif_options(&gif[..], options),
+        Err(Error::DecodingError(_))
+    ));
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_memory_limit(gif::MemoryLimit(160 * 128 * 4));
+    assert_eq!(
+        decode_octocart_with_gif_options(&gif[..], options).unwrap(),
+        payload
+    );
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    assert_eq!(
+        decode_octocart_with_gif_options(&gif[..], options).unwrap(),
+        payload
+    );
+}
+
+#[test]
+fn diff_program() {
+    let cart = |program: &str| {
+        OctoCart::from_str(&json!({ "program": program, "options": {} }).to_string()).unwrap()
+    };
+    let old = cart(": main\n  v0 := 1\n  v1 := 2\n  v2 := 3\n  v3 := 4\n  v4 := 5\n  v5 := 6\n  v6 := 7\n  v7 := 8\n  v8 := 9\n  loop again\n");
+    let new = cart(": main\n  v0 := 10\n  v1 := 2\n  v2 := 3\n  v3 := 4\n  v4 := 5\n  v5 := 6\n  v6 := 7\n  v7 := 8\n  v8 := 9\n  v9 := 0\n  loop again\n");
+    assert_eq!(
+        old.diff_program(&new),
+        "--- a/program.8o\n+++ b/program.8o\n\
+         @@ -1,5 +1,5 @@\n : main\n-  v0 := 1\n+  v0 := 10\n   v1 := 2\n   v2 := 3\n   v3 := 4\n\
+         @@ -8,4 +8,5 @@\n   v6 := 7\n   v7 := 8\n   v8 := 9\n+  v9 := 0\n   loop again\n"
+    );
+    assert_eq!(old.diff_program(&old), "");
+
+    // Adding a program to an empty one is a single hunk with an empty old range
+    assert_eq!(
+        cart("").diff_program(&cart(": main")),
+        "--- a/program.8o\n+++ b/program.8o\n@@ -0,0 +1,1 @@\n+: main\n"
+    );
+}
+
+#[test]
+fn decode_single_frame() {
+    // 28 bytes fit in the first 8x8 frame after the header, and 32 in each of the others
+    let data: Vec<u8> = (0..80).collect();
+    let gif = build_cart(&data, 8, 8);
+    let mut cursor = std::io::Cursor::new(gif);
+    assert_eq!(decode_frame(&mut cursor, 0).unwrap(), &data[..28]);
+    assert_eq!(decode_frame(&mut cursor, 1).unwrap(), &data[28..60]);
+    assert_eq!(decode_frame(&mut cursor, 2).unwrap(), &data[60..]);
+    assert!(decode_frame(&mut cursor, 3).unwrap().is_empty());
+}
+
+#[test]
+fn utf8_strictness() {
+    let mut payload = br#"{"program":": main # caf"#.to_vec();
+    payload.push(0xE9);
+    payload.extend_from_slice(br#"","options":{}}"#);
+    let gif = build_cart(&payload, 16, 16);
+
+    // Strict decoding is the default
+    assert!(matches!(
+        decode_octocart(&gif[..]),
+        Err(Error::Utf8Error(_))
+    ));
+
+    let options = DecodeOptions {
+        lossy_utf8: true,
+        ..DecodeOptions::default()
+    };
+    let decoded = decode_octocart_with_options(&gif[..], &options).unwrap();
+    let cart = OctoCart::from_str(&decoded).unwrap();
+    assert_eq!(cart.program, ": main # caf\u{FFFD}");
+}
+
+#[test]
+fn separate_options_roundtrip() {
+    let mut cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    cart.program = ": main\n".repeat(300);
+    let options = EncodeOptions {
+        separate_options: true,
+        ..EncodeOptions::default()
+    };
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &options).unwrap();
+    assert_eq!(cart_info(&gif[..]).unwrap().frame_count, 2);
+    let payload = decode_octocart(&gif[..]).unwrap();
+    assert!(payload.starts_with("{\"options\":{"));
+    assert_eq!(OctoCart::from_str(&payload).unwrap(), cart);
+
+    // The program starts at the beginning of the second frame
+    let mut cursor = std::io::Cursor::new(&gif);
+    assert!(decode_frame(&mut cursor, 1)
+        .unwrap()
+        .starts_with(b"\"program\":\": main\\n"));
+}
+
+#[test]
+fn options_only_fast_read() {
+    let mut cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    cart.program = (0..500).map(|i| format!(": label{}\n", i)).collect();
+    let options = EncodeOptions {
+        separate_options: true,
+        ..EncodeOptions::default()
+    };
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &options).unwrap();
+
+    // Cut the GIF off in the middle of the program's frame, which is never read
+    let truncated = &gif[..gif.len() * 3 / 4];
+    assert!(decode_octocart(truncated).is_err());
+    assert_eq!(decode_options_only(truncated).unwrap(), cart.options);
+
+    // Carts with the default layout are decoded in full
+    let file = std::fs::File::open("tests/test_carts/minimal.gif").unwrap();
+    assert_eq!(decode_options_only(file).unwrap(), cart.options);
+}
+
+#[test]
+fn title_and_author() {
+    // Existing carts have no metadata, and it isn't added to their payload
+    let mut cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    assert_eq!(cart.title, None);
+    assert_eq!(cart.author, None);
+    let payload = cart.to_string();
+    assert!(!payload.contains("\"title\"") && !payload.contains("\"author\""));
+
+    cart.title = Some("Minimal".to_string());
+    cart.author = Some("Octo Developer".to_string());
+    let json: Value = serde_json::from_str(&cart.to_string()).unwrap();
+    assert_eq!(json["title"], "Minimal");
+    assert_eq!(json["author"], "Octo Developer");
+
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &EncodeOptions::default()).unwrap();
+    let decoded = OctoCart::from_str(&decode_octocart(&gif[..]).unwrap()).unwrap();
+    assert_eq!(decoded, cart);
+}
+
+#[test]
+fn string_literals() {
+    let program = r#"
+        # "not a string"
+        :stringmode print "ABCDEFGHIJKLMNOPQRSTUVWXYZ" { v0 := VALUE print-char }
+        : main
+            text "Say \"hi\"\tthere\n"
+            text "C:\\OCTO" # backslash
+    "#;
+    let cart =
+        OctoCart::from_str(&json!({ "program": program, "options": {} }).to_string()).unwrap();
+    assert_eq!(
+        cart.string_literals(),
+        vec![
+            "ABCDEFGHIJKLMNOPQRSTUVWXYZ".to_string(),
+            "Say \"hi\"\tthere\n".to_string(),
+            "C:\\OCTO".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn max_frames_limit() {
+    // 2000 bytes spread over 8x8 frames of 32 bytes each
+    let data = vec![b' '; 2000];
+    let gif = build_cart(&data, 8, 8);
+    assert_eq!(cart_info(&gif[..]).unwrap().frame_count, 63);
+
+    let options = DecodeOptions {
+        max_frames: 10,
+        ..DecodeOptions::default()
+    };
+    assert!(matches!(
+        decode_octocart_with_options(&gif[..], &options),
+        Err(Error::TooManyFrames)
+    ));
+
+    let options = DecodeOptions {
+        max_frames: 63,
+        ..DecodeOptions::default()
+    };
+    assert_eq!(
+        decode_octocart_with_options(&gif[..], &options)
+            .unwrap()
+            .len(),
+        2000
+    );
+    assert_eq!(decode_octocart(&gif[..]).unwrap().len(), 2000);
+}
+
+#[test]
+fn clone_with_program() {
+    let mut cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    cart.title = Some("Minimal".to_string());
+    let edited = cart.clone_with_program(String::from(": main\n  loop again"));
+    assert_eq!(edited.program, ": main\n  loop again");
+    assert_eq!(edited.options, cart.options);
+    assert_eq!(edited.title, cart.title);
+    assert_eq!(cart.program, ": main");
+}
+
+#[test]
+fn start_address() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    assert_eq!(cart.options.start_address, None);
+    assert_eq!(cart.start_address(), 0x200);
+
+    let mut cart = cart.clone_with_program(": main");
+    cart.merge_from_json(r#"{"startAddress":"1536"}"#).unwrap();
+    assert_eq!(cart.start_address(), 0x600);
+    assert_eq!(cart.options_json_value()["startAddress"], 1536);
+
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &EncodeOptions::default()).unwrap();
+    let decoded = OctoCart::from_str(&decode_octocart(&gif[..]).unwrap()).unwrap();
+    assert_eq!(decoded.options.start_address, Some(0x600));
+}
+
+#[test]
+fn decode_full() {
+    let gif = std::fs::read("tests/test_carts/minimal.gif").unwrap();
+    let (bytes, cart) = decart::decode_full(&gif[..]).unwrap();
+    assert_eq!(bytes, decode_octocart(&gif[..]).unwrap().into_bytes());
+    assert_eq!(
+        OctoCart::from_str(std::str::from_utf8(&bytes).unwrap()).unwrap(),
+        cart
+    );
+    // The raw bytes keep keys that the parsed cart doesn't
+    assert_ne!(bytes, cart.to_string().into_bytes());
+}
+
+#[test]
+fn validate_color_visibility() {
+    let mut cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    assert!(cart.validate().is_empty());
+
+    cart.merge_from_json(r##"{"fillColor":"#996600","fillColor2":"#996600"}"##)
+        .unwrap();
+    assert_eq!(
+        cart.validate(),
+        vec!["fillColor is the same as backgroundColor, so graphics are invisible".to_string()]
+    );
+
+    // The second fill color only matters for programs that draw in the second plane
+    let cart = cart.clone_with_program(": main\n  plane 2\n  sprite v0 v1 0");
+    assert_eq!(cart.validate().len(), 2);
+}
+
+#[test]
+fn apply_options_from() {
+    let source: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let mut cart =
+        OctoCart::from_str(r#"{"program":": main\n  loop again","options":{"tickrate":1000}}"#)
+            .unwrap();
+    cart.apply_options_from(&source);
+    assert_eq!(cart.options, source.options);
+    assert_eq!(cart.program, ": main\n  loop again");
+}
+
+#[test]
+fn termin8_config() {
+    let mut cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let expected = std::fs::read_to_string("tests/test_carts/minimal.octo.rc").unwrap();
+    assert_eq!(cart.to_termin8_config(), expected);
+
+    for (font, name) in [
+        (octopt::Font::Dream6800, "dream_6800"),
+        (octopt::Font::Eti660, "eti_660"),
+        (octopt::Font::AKouZ1, "akouz1"),
+    ] {
+        cart.options.font_style = font;
+        assert!(cart
+            .to_termin8_config()
+            .contains(&format!("\ncore.font={}\n", name)));
+    }
+}
+
+#[test]
+fn minimum_palette_size() {
+    // Every byte survives a roundtrip through a palette with exactly the minimum number of colors
+    let payload: Vec<u8> = (0..=255).collect();
+    let gif = build_cart(&payload, 32, 32);
+    let mut decoder = Decoder::new(&gif[..], &DecodeOptions::default()).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap(), payload);
+
+    // Encoded carts use the minimum palette
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let mut encoded = Vec::new();
+    encode_octocart(&mut encoded, &cart, &EncodeOptions::default()).unwrap();
+    let decoder = gif::DecodeOptions::new().read_info(&encoded[..]).unwrap();
+    assert_eq!(
+        decoder.global_palette().unwrap().len(),
+        3 * MIN_PALETTE_SIZE
+    );
+
+    // A monochrome palette can't carry every nybble, so it's refused
+    let monochrome = [0, 0, 0, 1, 3, 1];
+    assert!(matches!(
+        decode_with_palette(&gif[..], &monochrome),
+        Err(Error::PaletteError)
+    ));
+    // As is an RGBA palette with 12 colors, even though it has as many bytes as 16 RGB colors
+    let rgba: Vec<u8> = nybble_palette()
+        .chunks(3)
+        .take(12)
+        .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xFF])
+        .collect();
+    assert!(matches!(
+        decode_with_palette(&gif[..], &rgba),
+        Err(Error::PaletteError)
+    ));
+}
+
+#[test]
+fn program_stats() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    assert_eq!(cart.program_line_count(), 1);
+    assert_eq!(cart.program_char_count(), ": main".len());
+
+    let cart = cart.clone_with_program("");
+    assert_eq!(cart.program_line_count(), 0);
+    assert_eq!(cart.program_char_count(), 0);
+}
+
+#[test]
+fn control_characters_lenient() {
+    let payload = "{\"program\":\": main\\nloop again\",\r\n\x0c\"options\":{\"tickrate\":7,\x00\x1b\"fontStyle\":\"schip\"}}";
+    assert!(OctoCart::from_str(payload).is_err());
+    let (cart, warnings) = OctoCart::from_str_lenient(payload).unwrap();
+    assert_eq!(cart.options.tickrate, Some(7));
+    assert_eq!(cart.options.font_style, octopt::Font::Schip);
+    assert_eq!(
+        warnings,
+        vec!["removed 3 control character(s) between JSON tokens"]
+    );
+}
+
+#[test]
+fn target_size_padding() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let mut unpadded = Vec::new();
+    encode_octocart(&mut unpadded, &cart, &EncodeOptions::default()).unwrap();
+
+    let target_size = 8192;
+    let mut padded = Vec::new();
+    let options = EncodeOptions {
+        target_size: Some(target_size),
+        ..EncodeOptions::default()
+    };
+    encode_octocart(&mut padded, &cart, &options).unwrap();
+    assert!(padded.len() >= target_size);
+    // A padding frame compresses to much less than the frame carrying the payload
+    assert!(padded.len() < target_size + unpadded.len());
+    assert!(cart_info(&padded[..]).unwrap().frame_count > 1);
+    assert_eq!(decode_octocart(&padded[..]).unwrap(), cart.to_string());
+
+    // A GIF that's already large enough isn't padded
+    let mut same = Vec::new();
+    let options = EncodeOptions {
+        target_size: Some(unpadded.len()),
+        ..EncodeOptions::default()
+    };
+    encode_octocart(&mut same, &cart, &options).unwrap();
+    assert_eq!(same, unpadded);
+}
+
+#[test]
+fn quirks_vs_platform() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    assert_eq!(
+        cart.quirks_vs_platform(octopt::Platform::Vip),
+        vec![("vfOrderQuirks".to_string(), false, true)]
+    );
+    assert_eq!(
+        cart.quirks_vs_platform(octopt::Platform::Octo),
+        vec![
+            ("logicQuirks".to_string(), true, false),
+            ("clipQuirks".to_string(), true, false),
+            ("vBlankQuirks".to_string(), true, false),
+            ("resClearQuirks".to_string(), false, true),
+        ]
+    );
+}
+
+#[test]
+fn cover_frames_are_skipped() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    let art: Vec<u8> = (0..32 * 32).map(|i| (i % 7) as u8).collect();
+    let mut cover = gif::Frame::from_indexed_pixels(32, 32, &art, Some(6));
+    cover.palette = Some((0..7).flat_map(|i| [i * 36, 0, 255 - i * 36]).collect());
+    let options = EncodeOptions {
+        frame_dimensions: vec![(16, 16)],
+        cover_frames: vec![(2, cover.clone()), (0, cover.clone()), (99, cover.clone())],
+        ..EncodeOptions::default()
+    };
+    let mut gif = Vec::new();
+    encode_octocart(&mut gif, &cart, &options).unwrap();
+
+    // The payload needs 4 frames, and the cover frames are the first, third and last of them all
+    let info = cart_info(&gif[..]).unwrap();
+    assert_eq!((info.width, info.height), (32, 32));
+    assert_eq!(info.frame_count, 7);
+    assert_eq!(info.payload_length as usize, cart.to_string().len());
+    let mut decoder = gif::DecodeOptions::new().read_info(&gif[..]).unwrap();
+    let widths: Vec<u16> =
+        std::iter::from_fn(|| decoder.read_next_frame().unwrap().map(|f| f.width)).collect();
+    assert_eq!(widths, [32, 16, 32, 16, 16, 16, 32]);
+
+    assert_eq!(decode_octocart(&gif[..]).unwrap(), cart.to_string());
+    let mut rgba = gif::DecodeOptions::new();
+    rgba.set_color_output(gif::ColorOutput::RGBA);
+    assert_eq!(
+        decode_octocart_with_gif_options(&gif[..], rgba).unwrap(),
+        cart.to_string()
+    );
+
+    // Cover frames need a transparent color to be marked with
+    cover.transparent = None;
+    let options = EncodeOptions {
+        cover_frames: vec![(0, cover)],
+        ..EncodeOptions::default()
+    };
+    assert!(matches!(
+        encode_octocart(Vec::new(), &cart, &options),
+        Err(Error::CoverFrameError)
+    ));
+}
+
+#[test]
+fn validate_all() {
+    let cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    assert!(cart.validate_all().is_clean());
+
+    let cart = OctoCart::from_str(
+        r##"{"program":"\n","options":{"tickrate":0,"startAddress":4096,"maxSize":65024,
+        "fillColor":"#112233","backgroundColor":"#112233","clipCollisionQuirks":true}}"##,
+    )
+    .unwrap();
+    let report = cart.validate_all();
+    assert_eq!(
+        report.errors,
+        vec![
+            "program is empty",
+            "tickrate is 0, so the program never runs",
+            "startAddress 0x1000 leaves no room for maxSize 65024 bytes in 64 KiB of memory",
+        ]
+    );
+    assert_eq!(
+        report.warnings,
+        vec![
+            "fillColor is the same as backgroundColor, so graphics are invisible",
+            "clipCollisionQuirks has no effect, since clipQuirks is disabled",
+        ]
+    );
+}
+
+#[test]
+fn decode_red_only_layout() {
+    // A buggy encoder stores one bit per pixel in the red channel, most significant bit first
+    let payload = r#"{"program":": main\n  loop again","options":{"tickrate":20}}"#;
+    let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+    bytes.extend_from_slice(payload.as_bytes());
+    let pixels: Vec<u8> = bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1))
+        .collect();
+    let gif = build_gif(&[0x80, 0x40, 0x20, 0x81, 0x40, 0x20], &[(32, 24, &pixels)]);
+
+    assert_ne!(decode_octocart(&gif[..]).unwrap_or_default(), payload);
+    assert_eq!(
+        decode_with_layout(&gif[..], BitLayout::RedOnly).unwrap(),
+        payload
+    );
+    let cart = OctoCart::from_str(payload).unwrap();
+    let mut octo = Vec::new();
+    encode_octocart(&mut octo, &cart, &EncodeOptions::default()).unwrap();
+    assert_eq!(
+        decode_with_layout(&octo[..], BitLayout::Octo).unwrap(),
+        cart.to_string()
+    );
+}
+
+#[test]
+fn summary_card() {
+    let mut cart: OctoCart = from_file("tests/test_carts/minimal.gif").unwrap();
+    assert_eq!(cart.closest_platform(), "VIP");
+    cart.title = Some("Minimal".to_string());
+    let card = cart.summary_card();
+    assert!(card.starts_with("Minimal\n"));
+    assert!(!card.contains("Author:"));
+    assert!(card.contains("Platform: VIP\n"));
+    assert!(card.contains("Tickrate: 7\n"));
+    // Swatches for the six colors, starting with the fill color #FFCC00
+    assert!(card.contains("Colors:   \x1b[48;2;255;204;0m  \x1b[0m "));
+    assert_eq!(card.matches("\x1b[48;2;").count(), 6);
+    assert!(card.ends_with("Program:  1 line, 6 characters\n"));
+    let card = cart
+        .clone_with_program(": main\n  loop again")
+        .summary_card();
+    assert!(card.ends_with("Program:  2 lines, 19 characters\n"));
+}